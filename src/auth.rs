@@ -0,0 +1,369 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, Request},
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::{
+    error::SchedulerError,
+    event::{EventState, Events},
+    scheduler::Judge,
+};
+
+/// Random bytes in every judge token and generated key.
+const TOKEN_BYTES: usize = 32;
+
+/// How long a magic link stays valid after it is issued.
+pub const MAGIC_LINK_MS: u64 = 7 * 24 * 60 * 60 * 1000;
+
+/// A new random secret, URL-safe base64 encoded.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Tokens are only ever stored as this hash, so a leaked database does not
+/// leak working credentials.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Server-wide secrets: the keys admin routes require and the key magic links
+/// are signed with.
+pub struct Auth {
+    admin_key_hashes: Vec<String>,
+    signing_key: Vec<u8>,
+    /// Where the server is reachable from judges' browsers, e.g.
+    /// `https://judging.example.com`. Magic links are only issued when set.
+    public_url: Option<String>,
+}
+
+impl Auth {
+    /// Each admin key is either the key itself or `sha256:` followed by the
+    /// hex digest of the key, so config files need not hold the plain key.
+    pub fn new(admin_keys: &[String], signing_key: Vec<u8>, public_url: Option<String>) -> Auth {
+        Auth {
+            admin_key_hashes: admin_keys
+                .iter()
+                .map(|key| match key.strip_prefix("sha256:") {
+                    Some(hash) => hash.to_ascii_lowercase(),
+                    None => hash_token(key),
+                })
+                .collect(),
+            signing_key,
+            public_url: public_url.map(|url| url.trim_end_matches('/').to_string()),
+        }
+    }
+
+    /// Comparing hashes keeps the comparison time independent of how much of
+    /// the key was guessed right.
+    pub fn is_admin_key(&self, key: &str) -> bool {
+        let hash = hash_token(key);
+        self.admin_key_hashes.contains(&hash)
+    }
+
+    fn mac(&self, event_id: &str, judge: &Judge, expires_at_ms: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.signing_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(
+            format!(
+                "{}\n{}\n{}\n{}",
+                event_id, judge.id, judge.email, expires_at_ms
+            )
+            .as_bytes(),
+        );
+        mac
+    }
+
+    /// A link a judge can open to get a fresh token, signed over the event, the
+    /// judge and their email so it stops working if the email changes.
+    pub fn magic_link(&self, event_id: &str, judge: &Judge, now_ms: u64) -> Option<String> {
+        let public_url = self.public_url.as_ref()?;
+        let expires_at_ms = now_ms + MAGIC_LINK_MS;
+        let sig = self.mac(event_id, judge, expires_at_ms).finalize();
+        Some(format!(
+            "{}/events/{}/judge/login?judge={}&expires={}&sig={}",
+            public_url,
+            event_id,
+            judge.id,
+            expires_at_ms,
+            URL_SAFE_NO_PAD.encode(sig.into_bytes())
+        ))
+    }
+
+    pub fn verify_magic_link(
+        &self,
+        event_id: &str,
+        judge: &Judge,
+        expires_at_ms: u64,
+        sig: &str,
+        now_ms: u64,
+    ) -> bool {
+        let Ok(sig) = URL_SAFE_NO_PAD.decode(sig) else {
+            return false;
+        };
+        now_ms < expires_at_ms
+            && self
+                .mac(event_id, judge, expires_at_ms)
+                .verify_slice(&sig)
+                .is_ok()
+    }
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Who a request comes from, going by its `Authorization: Bearer` header:
+/// an admin holding an admin key, a judge holding one of the event's judge
+/// tokens, or anyone at all. Unrecognised credentials are rejected rather than
+/// downgraded to public.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Caller {
+    Public,
+    Judge(Judge),
+    Admin,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Caller
+where
+    Events: FromRef<S>,
+    Arc<Auth>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = SchedulerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(token) = bearer_token(parts) else {
+            if parts.headers.contains_key(AUTHORIZATION) {
+                return Err(SchedulerError::Unauthorized(
+                    "expected `Authorization: Bearer <token>`".to_string(),
+                ));
+            }
+            return Ok(Caller::Public);
+        };
+        if Arc::<Auth>::from_ref(state).is_admin_key(token) {
+            return Ok(Caller::Admin);
+        }
+        let token = token.to_string();
+        EventState::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|EventState(scheduler)| scheduler.judge_for_token(&token))
+            .map(Caller::Judge)
+            .ok_or_else(|| SchedulerError::Unauthorized("invalid credentials".to_string()))
+    }
+}
+
+/// The judge whose token was sent as `Authorization: Bearer <token>`, as long
+/// as they are still active.
+pub struct AuthJudge(pub Judge);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthJudge
+where
+    Events: FromRef<S>,
+    Arc<Auth>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = SchedulerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Caller::from_request_parts(parts, state).await? {
+            Caller::Judge(judge) if !judge.active => Err(SchedulerError::Forbidden(
+                "judge has been deactivated".to_string(),
+            )),
+            Caller::Judge(judge) => Ok(AuthJudge(judge)),
+            Caller::Admin => Err(SchedulerError::Forbidden(
+                "only judges can do this".to_string(),
+            )),
+            Caller::Public => Err(SchedulerError::Unauthorized(
+                "missing judge token".to_string(),
+            )),
+        }
+    }
+}
+
+/// Middleware letting only admins through to the routes it is layered on.
+pub async fn require_admin<B>(
+    caller: Caller,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, SchedulerError> {
+    match caller {
+        Caller::Admin => Ok(next.run(request).await),
+        Caller::Judge(_) => Err(SchedulerError::Forbidden(
+            "only admins can do this".to_string(),
+        )),
+        Caller::Public => Err(SchedulerError::Unauthorized(
+            "missing admin key".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use ntest::timeout;
+
+    use super::*;
+    use crate::event::{EventConfig, DEFAULT_EVENT};
+
+    fn auth() -> Auth {
+        Auth::new(
+            &[
+                "admin-key".to_string(),
+                format!("sha256:{}", hash_token("hashed-key")),
+            ],
+            b"signing key".to_vec(),
+            Some("https://judging.example.com/".to_string()),
+        )
+    }
+
+    fn parts(authorization: Option<&str>) -> Parts {
+        let mut request = Request::builder().uri("/matches/for_judge");
+        if let Some(value) = authorization {
+            request = request.header(AUTHORIZATION, value);
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    fn query(link: &str, key: &str) -> String {
+        link.split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix(&format!("{}=", key)))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_tokens() {
+        let token = new_token();
+        assert_ne!(token, new_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+        assert!(auth().is_admin_key("admin-key"));
+        assert!(auth().is_admin_key("hashed-key"));
+        assert!(!auth().is_admin_key("admin-ke"));
+        assert!(!auth().is_admin_key(&hash_token("hashed-key")));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_magic_link() {
+        let auth = auth();
+        let judge = Judge::new("judge@example.com".to_string());
+        let link = auth.magic_link("finals", &judge, 1_000).unwrap();
+        assert!(link.starts_with("https://judging.example.com/events/finals/judge/login?"));
+        assert_eq!(query(&link, "judge"), judge.id);
+
+        let expires: u64 = query(&link, "expires").parse().unwrap();
+        let sig = query(&link, "sig");
+        assert_eq!(expires, 1_000 + MAGIC_LINK_MS);
+        assert!(auth.verify_magic_link("finals", &judge, expires, &sig, 2_000));
+        // expired, for another event, for a changed email, extended, or forged
+        assert!(!auth.verify_magic_link("finals", &judge, expires, &sig, expires));
+        assert!(!auth.verify_magic_link("default", &judge, expires, &sig, 2_000));
+        let moved = Judge::from_id("new@example.com".to_string(), judge.id.clone());
+        assert!(!auth.verify_magic_link("finals", &moved, expires, &sig, 2_000));
+        assert!(!auth.verify_magic_link("finals", &judge, expires + 1, &sig, 2_000));
+        assert!(!auth.verify_magic_link("finals", &judge, expires, "AAAA", 2_000));
+
+        let no_url = Auth::new(&[], b"signing key".to_vec(), None);
+        assert!(no_url.magic_link("finals", &judge, 1_000).is_none());
+    }
+
+    #[derive(Clone)]
+    struct TestState {
+        events: Events,
+        auth: Arc<Auth>,
+    }
+
+    impl FromRef<TestState> for Events {
+        fn from_ref(state: &TestState) -> Events {
+            state.events.clone()
+        }
+    }
+
+    impl FromRef<TestState> for Arc<Auth> {
+        fn from_ref(state: &TestState) -> Arc<Auth> {
+            state.auth.clone()
+        }
+    }
+
+    #[tokio::test]
+    #[timeout(1000)]
+    async fn test_callers() {
+        let events = Events::in_memory(EventConfig::default());
+        let state = events.get(DEFAULT_EVENT).unwrap().state;
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        let token = state.issue_token(&judge.id);
+        let bearer = format!("Bearer {}", token);
+        let app = TestState {
+            events,
+            auth: Arc::new(auth()),
+        };
+
+        let caller = |header| {
+            let app = app.clone();
+            async move { Caller::from_request_parts(&mut parts(header), &app).await }
+        };
+        assert_eq!(caller(None).await, Ok(Caller::Public));
+        assert_eq!(
+            caller(Some(&bearer)).await,
+            Ok(Caller::Judge(judge.clone()))
+        );
+        assert_eq!(caller(Some("Bearer admin-key")).await, Ok(Caller::Admin));
+        assert_eq!(caller(Some("Bearer hashed-key")).await, Ok(Caller::Admin));
+        for header in ["Bearer nope", token.as_str()] {
+            assert!(matches!(
+                caller(Some(header)).await,
+                Err(SchedulerError::Unauthorized(_))
+            ));
+        }
+
+        let AuthJudge(found) = AuthJudge::from_request_parts(&mut parts(Some(&bearer)), &app)
+            .await
+            .unwrap();
+        assert_eq!(found, judge);
+        assert!(matches!(
+            AuthJudge::from_request_parts(&mut parts(None), &app).await,
+            Err(SchedulerError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            AuthJudge::from_request_parts(&mut parts(Some("Bearer admin-key")), &app).await,
+            Err(SchedulerError::Forbidden(_))
+        ));
+
+        // reissuing replaces the old token
+        let reissued = format!("Bearer {}", state.issue_token(&judge.id));
+        assert_eq!(
+            caller(Some(&reissued)).await,
+            Ok(Caller::Judge(judge.clone()))
+        );
+        assert!(matches!(
+            caller(Some(&bearer)).await,
+            Err(SchedulerError::Unauthorized(_))
+        ));
+        state.revoke_tokens(&judge.id);
+        assert!(matches!(
+            caller(Some(&reissued)).await,
+            Err(SchedulerError::Unauthorized(_))
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+/// Ratings are reported on the familiar 400-point logistic scale around this value.
+pub const BASE_RATING: f64 = 1500.0;
+
+const SCALE: f64 = 400.0 / std::f64::consts::LN_10;
+
+/// Games every player is assumed to have drawn against a virtual opponent of
+/// strength 1. Keeps strengths finite for players who never won or never lost
+/// and ties separate components of the comparison graph to a common scale.
+const PRIOR_GAMES: f64 = 1.0;
+
+const MAX_ITERATIONS: usize = 10_000;
+const TOLERANCE: f64 = 1e-10;
+
+/// One comparison: `score` is what `a` earned against `b` (1 win, 0 loss).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Game {
+    pub a: usize,
+    pub b: usize,
+    pub score: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fit {
+    /// Log-strength of each player.
+    pub theta: Vec<f64>,
+    /// Standard error of each entry in `theta`.
+    pub std_errors: Vec<f64>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+impl Fit {
+    pub fn rating(&self, i: usize) -> f64 {
+        BASE_RATING + SCALE * self.theta[i]
+    }
+
+    pub fn rating_std_error(&self, i: usize) -> f64 {
+        SCALE * self.std_errors[i]
+    }
+}
+
+/// Maximum-likelihood Bradley–Terry strengths for `n` players using Hunter's
+/// MM algorithm. The result does not depend on the order of `games`.
+pub fn fit(n: usize, games: &[Game]) -> Fit {
+    let mut wins = vec![PRIOR_GAMES / 2.0; n];
+    let mut counts = vec![vec![0f64; n]; n];
+    for game in games {
+        if game.a == game.b {
+            continue;
+        }
+        wins[game.a] += game.score;
+        wins[game.b] += 1.0 - game.score;
+        counts[game.a][game.b] += 1.0;
+        counts[game.b][game.a] += 1.0;
+    }
+
+    let mut p = vec![1f64; n];
+    let mut iterations = 0;
+    let mut converged = n == 0;
+    while iterations < MAX_ITERATIONS && !converged {
+        iterations += 1;
+        let mut next = vec![0f64; n];
+        for i in 0..n {
+            let mut denom = PRIOR_GAMES / (p[i] + 1.0);
+            for j in 0..n {
+                if counts[i][j] > 0.0 {
+                    denom += counts[i][j] / (p[i] + p[j]);
+                }
+            }
+            next[i] = wins[i] / denom;
+        }
+        converged = p
+            .iter()
+            .zip(&next)
+            .all(|(old, new)| (new.ln() - old.ln()).abs() < TOLERANCE);
+        p = next;
+    }
+
+    Fit {
+        theta: p.iter().map(|x| x.ln()).collect(),
+        std_errors: std_errors(&p, &counts),
+        iterations,
+        converged,
+    }
+}
+
+/// Square roots of the diagonal of the inverse Fisher information for the
+/// log-strengths.
+fn std_errors(p: &[f64], counts: &[Vec<f64>]) -> Vec<f64> {
+    let n = p.len();
+    let mut info = vec![vec![0f64; n]; n];
+    for i in 0..n {
+        info[i][i] = PRIOR_GAMES * p[i] / (p[i] + 1.0).powi(2);
+        for j in 0..n {
+            if i != j && counts[i][j] > 0.0 {
+                let w = counts[i][j] * p[i] * p[j] / (p[i] + p[j]).powi(2);
+                info[i][i] += w;
+                info[i][j] -= w;
+            }
+        }
+    }
+    invert(info)
+        .iter()
+        .enumerate()
+        .map(|(i, row)| row[i].max(0.0).sqrt())
+        .collect()
+}
+
+/// Gauss-Jordan inverse with partial pivoting. The information matrix is
+/// positive definite thanks to the prior, so this never meets a zero pivot.
+fn invert(mut a: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let n = a.len();
+    let mut inv: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))
+            .unwrap();
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        let d = a[col][col];
+        for j in 0..n {
+            a[col][j] /= d;
+            inv[col][j] /= d;
+        }
+        for row in 0..n {
+            if row != col && a[row][col] != 0.0 {
+                let factor = a[row][col];
+                for j in 0..n {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+    }
+    inv
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+
+    fn game(a: usize, b: usize, score: f64) -> Game {
+        Game { a, b, score }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_order_independent() {
+        let games = vec![
+            game(0, 1, 1.0),
+            game(1, 2, 1.0),
+            game(0, 2, 1.0),
+            game(2, 1, 0.0),
+            game(1, 0, 0.0),
+        ];
+        let reversed: Vec<Game> = games.iter().rev().copied().collect();
+
+        let a = fit(3, &games);
+        let b = fit(3, &reversed);
+        assert!(a.converged);
+        for i in 0..3 {
+            assert!((a.theta[i] - b.theta[i]).abs() < 1e-9);
+        }
+        assert!(a.rating(0) > a.rating(1) && a.rating(1) > a.rating(2));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_stationary_point() {
+        // at the maximum each player's expected score equals their actual score
+        let games = vec![
+            game(0, 1, 1.0),
+            game(0, 1, 0.0),
+            game(0, 1, 1.0),
+            game(1, 2, 1.0),
+            game(2, 0, 1.0),
+            game(2, 1, 0.0),
+        ];
+        let f = fit(3, &games);
+        let p: Vec<f64> = f.theta.iter().map(|t| t.exp()).collect();
+        for i in 0..3 {
+            let mut actual = PRIOR_GAMES / 2.0;
+            let mut expected = PRIOR_GAMES * p[i] / (p[i] + 1.0);
+            for g in &games {
+                if g.a == i {
+                    actual += g.score;
+                    expected += p[i] / (p[i] + p[g.b]);
+                } else if g.b == i {
+                    actual += 1.0 - g.score;
+                    expected += p[i] / (p[i] + p[g.a]);
+                }
+            }
+            assert!((actual - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_std_errors_shrink_with_data() {
+        let few = vec![game(0, 1, 1.0), game(1, 0, 1.0)];
+        let many: Vec<Game> = (0..40).map(|i| game(i % 2, 1 - i % 2, 1.0)).collect();
+
+        let a = fit(2, &few);
+        let b = fit(2, &many);
+        assert!(b.std_errors[0] < a.std_errors[0]);
+        assert!((b.theta[0] - b.theta[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_undefeated_stays_finite() {
+        let f = fit(2, &[game(0, 1, 1.0), game(0, 1, 1.0)]);
+        assert!(f.converged);
+        assert!(f.theta.iter().all(|t| t.is_finite()));
+        assert!(f.theta[0] > f.theta[1]);
+        assert_eq!(fit(0, &[]).theta.len(), 0);
+    }
+}
@@ -0,0 +1,268 @@
+use serde::Deserialize;
+use std::{collections::HashMap, fs, net::SocketAddr, path::Path, path::PathBuf};
+
+use crate::event::EventConfig;
+
+/// Environment variables and the setting each one overrides.
+const ENV_VARS: &[(&str, &str, Kind)] = &[
+    ("RANKER_BIND", "bind", Kind::Text),
+    ("RANKER_DATA_DIR", "data_dir", Kind::Text),
+    ("RANKER_ADMIN_KEY", "admin_keys", Kind::List),
+    ("RANKER_SECRET", "secret", Kind::Text),
+    ("RANKER_PUBLIC_URL", "public_url", Kind::Text),
+    ("RANKER_ENGINE", "event.engine", Kind::Text),
+    ("RANKER_RATING_MODE", "event.rating_mode", Kind::Text),
+    ("RANKER_PAIRING", "event.pairing", Kind::Text),
+    ("RANKER_MAX_ITEM_VIEWS", "event.max_item_views", Kind::Value),
+    ("RANKER_LEASE_SECS", "event.lease_secs", Kind::Value),
+    (
+        "RANKER_RATING_PERIOD_SECS",
+        "event.rating_period_secs",
+        Kind::Value,
+    ),
+    ("RANKER_SEED_ROUNDS", "event.seed_rounds", Kind::Value),
+];
+
+/// How the text of an environment variable becomes a setting.
+#[derive(Copy, Clone)]
+enum Kind {
+    /// Taken as is.
+    Text,
+    /// Split on commas.
+    List,
+    /// Read as a TOML value, so numbers and booleans keep their type.
+    Value,
+}
+
+/// Everything the binary is configured with. Each setting is taken from the
+/// first of these that has it: command-line flags, `RANKER_*` environment
+/// variables, the TOML file, the defaults below.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the API listens on.
+    pub bind: SocketAddr,
+    /// Holds one SQLite file per event.
+    pub data_dir: PathBuf,
+    /// Keys accepted for admin routes, plain or as `sha256:<hex>`. A key is
+    /// generated and logged when there are none.
+    pub admin_keys: Vec<String>,
+    /// Signs magic links. Without it a random key is used, and links stop
+    /// working when the server restarts.
+    pub secret: Option<String>,
+    /// Where the API is reachable from outside, used to build magic links.
+    pub public_url: Option<String>,
+    /// Used by events that do not set their own configuration, including the
+    /// default event.
+    pub event: EventConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            data_dir: PathBuf::from("data"),
+            admin_keys: vec![],
+            secret: None,
+            public_url: None,
+            event: EventConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Layers `file`, the `RANKER_*` variables in `env` and `overrides`, in
+    /// that order, over the defaults. Overrides are dotted setting names,
+    /// e.g. `event.elo.k`, with their values.
+    pub fn load(
+        file: Option<&Path>,
+        env: &HashMap<String, String>,
+        overrides: &[(String, toml::Value)],
+    ) -> Result<Config, String> {
+        let mut table = match file {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+                toml::from_str(&text)
+                    .map_err(|err| format!("invalid config file {}: {}", path.display(), err))?
+            }
+            None => toml::Table::new(),
+        };
+        for (var, key, kind) in ENV_VARS {
+            let Some(raw) = env.get(*var) else {
+                continue;
+            };
+            let value = match kind {
+                Kind::Text => toml::Value::String(raw.clone()),
+                Kind::List => toml::Value::Array(
+                    raw.split(',')
+                        .map(|s| toml::Value::String(s.trim().to_string()))
+                        .collect(),
+                ),
+                Kind::Value => parse_value(raw),
+            };
+            set(&mut table, key, value).map_err(|err| format!("{}: {}", var, err))?;
+        }
+        for (key, value) in overrides {
+            set(&mut table, key, value.clone())?;
+        }
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|err| format!("invalid configuration: {}", err))?;
+        config
+            .validate()
+            .map_err(|err| format!("invalid configuration: {}", err))?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.data_dir.as_os_str().is_empty() {
+            return Err("data_dir must not be empty".to_string());
+        }
+        if self.admin_keys.iter().any(|key| key.is_empty()) {
+            return Err("admin_keys must not contain empty keys".to_string());
+        }
+        if self.secret.as_deref() == Some("") {
+            return Err("secret must not be empty".to_string());
+        }
+        if let Some(url) = &self.public_url {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(format!(
+                    "public_url {} must start with http:// or https://",
+                    url
+                ));
+            }
+        }
+        self.event
+            .validate()
+            .map_err(|err| format!("event: {}", err))
+    }
+}
+
+/// Reads a value given on the command line or in the environment: anything
+/// TOML would read as a number, boolean, array or quoted string, and plain
+/// text otherwise.
+pub fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Sets the dotted setting `key` in `table`, creating tables along the way.
+fn set(table: &mut toml::Table, key: &str, value: toml::Value) -> Result<(), String> {
+    let (parents, last) = match key.rsplit_once('.') {
+        Some((parents, last)) => (parents.split('.').collect(), last),
+        None => (vec![], key),
+    };
+    let mut table = table;
+    for (i, part) in parents.iter().enumerate() {
+        let entry = table
+            .entry(part.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        table = entry
+            .as_table_mut()
+            .ok_or_else(|| format!("{} is not a table", parents[..=i].join(".")))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::rating::EngineKind;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_layering() {
+        let path = std::env::temp_dir().join(format!("ranker-{}.toml", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            "bind = \"0.0.0.0:8080\"\ndata_dir = \"/srv/ranker\"\n\n\
+             [event]\nengine = \"elo\"\nlease_secs = 120\n\n[event.elo]\nk = 24\n",
+        )
+        .unwrap();
+        let config = Config::load(
+            Some(&path),
+            &env(&[
+                ("RANKER_LEASE_SECS", "300"),
+                ("RANKER_ADMIN_KEY", "one, two"),
+                ("RANKER_SECRET", "1234"),
+                ("RANKER_SEED_ROUNDS", "4"),
+                ("HOME", "/root"),
+            ]),
+            &[
+                ("bind".to_string(), parse_value("127.0.0.1:9000")),
+                ("event.elo.k".to_string(), parse_value("16")),
+            ],
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.bind, SocketAddr::from(([127, 0, 0, 1], 9000)));
+        assert_eq!(config.data_dir, PathBuf::from("/srv/ranker"));
+        assert_eq!(config.admin_keys, vec!["one", "two"]);
+        assert_eq!(config.secret.as_deref(), Some("1234"));
+        assert_eq!(config.event.engine, EngineKind::Elo);
+        assert_eq!(config.event.lease_secs, 300);
+        assert_eq!(config.event.seed_rounds, 4);
+        assert_eq!(config.event.elo.k, 16.0);
+        // settings nobody gave keep their defaults
+        assert_eq!(config.event.elo.scale, 400.0);
+        assert_eq!(config.event.pairing, EventConfig::default().pairing);
+
+        assert_eq!(
+            Config::load(None, &HashMap::new(), &[]).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_invalid_configuration() {
+        for (vars, overrides) in [
+            (vec![("RANKER_BIND", "localhost")], vec![]),
+            (vec![("RANKER_LEASE_SECS", "0")], vec![]),
+            (vec![("RANKER_ENGINE", "trueskill")], vec![]),
+            (vec![("RANKER_PUBLIC_URL", "ranker.example.com")], vec![]),
+            (vec![], vec![("event.elo.k", "-1")]),
+            (vec![], vec![("event.colour", "\"red\"")]),
+            (vec![], vec![("bind.port", "80")]),
+        ] {
+            let overrides: Vec<(String, toml::Value)> = overrides
+                .iter()
+                .map(|(k, v)| (k.to_string(), parse_value(v)))
+                .collect();
+            assert!(
+                Config::load(None, &env(&vars), &overrides).is_err(),
+                "{:?} {:?}",
+                vars,
+                overrides
+            );
+        }
+        let missing = Path::new("/nonexistent/ranker.toml");
+        assert!(Config::load(Some(missing), &HashMap::new(), &[])
+            .unwrap_err()
+            .contains("cannot read"));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_parse_value() {
+        assert_eq!(parse_value("12"), toml::Value::Integer(12));
+        assert_eq!(parse_value("0.5"), toml::Value::Float(0.5));
+        assert_eq!(parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(parse_value("\"12\""), toml::Value::String("12".to_string()));
+        assert_eq!(parse_value("elo"), toml::Value::String("elo".to_string()));
+    }
+}
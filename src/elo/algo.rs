@@ -1,32 +1,85 @@
 pub enum Winner {
     P1,
     P2,
+    Draw,
+    /// P1's share of the point, between 0 and 1.
+    Score(f64),
+}
+
+impl Winner {
+    fn score_p1(&self) -> f64 {
+        match self {
+            Winner::P1 => 1.0,
+            Winner::P2 => 0.0,
+            Winner::Draw => 0.5,
+            Winner::Score(s) => *s,
+        }
+    }
 }
 
 pub const K: f64 = 30.0;
 
+/// Rating gap at which the stronger player is expected to score ten times as
+/// much as the weaker one.
+pub const SCALE: f64 = 400.0;
+
 pub const INITIAL_ELO: f64 = 1000.0;
 
-fn calc_new_rating(old_rating: f64, expected: f64, k: f64, win: bool) -> f64 {
-    if win {
-        old_rating + k * (1.0 - expected)
-    } else {
-        old_rating + k * (0.0 - expected)
-    }
+fn calc_new_rating(old_rating: f64, expected: f64, k: f64, score: f64) -> f64 {
+    old_rating + k * (score - expected)
 }
 
-pub fn calculate(r1: f64, r2: f64, k: f64, winner: Winner) -> (f64, f64) {
-    let p1 = 1.0 / (1.0 + ((r1 - r2) / 400.0).powi(10));
-    let p2 = 1.0 / (1.0 + ((r2 - r1) / 400.0).powi(10));
-
-    return match winner {
-        Winner::P1 => (
-            calc_new_rating(r1, p1, k, true),
-            calc_new_rating(r2, p2, k, false),
-        ),
-        Winner::P2 => (
-            calc_new_rating(r1, p1, k, false),
-            calc_new_rating(r2, p2, k, true),
-        ),
-    };
+/// Expected score of a player rated `r1` against one rated `r2`.
+pub fn expected(r1: f64, r2: f64, scale: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((r2 - r1) / scale))
+}
+
+/// New ratings after one game. Each player moves by their own K-factor, so the
+/// points gained and lost only balance when `k1` equals `k2`.
+pub fn calculate(r1: f64, r2: f64, k1: f64, k2: f64, scale: f64, winner: Winner) -> (f64, f64) {
+    let p1 = expected(r1, r2, scale);
+    let p2 = expected(r2, r1, scale);
+    let s1 = winner.score_p1();
+
+    (
+        calc_new_rating(r1, p1, k1, s1),
+        calc_new_rating(r2, p2, k2, 1.0 - s1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+
+    #[test]
+    #[timeout(100)]
+    fn test_expected() {
+        assert_eq!(expected(1200.0, 1200.0, SCALE), 0.5);
+        // a gap of one scale means odds of ten to one
+        assert!((expected(1600.0, 1200.0, SCALE) - 10.0 / 11.0).abs() < 1e-12);
+        assert!((expected(1200.0, 1600.0, SCALE) - 1.0 / 11.0).abs() < 1e-12);
+        assert!((expected(1300.0, 1200.0, 200.0) - expected(1400.0, 1200.0, SCALE)).abs() < 1e-12);
+        // the stronger player is always favoured, however small the gap
+        assert!(expected(1201.0, 1200.0, SCALE) > 0.5);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_calculate() {
+        let (a, b) = calculate(1000.0, 1000.0, K, K, SCALE, Winner::P1);
+        assert_eq!((a, b), (1015.0, 985.0));
+        let (a, b) = calculate(1000.0, 1000.0, K, K, SCALE, Winner::Draw);
+        assert_eq!((a, b), (1000.0, 1000.0));
+
+        // an upset moves ratings further than an expected result
+        let (upset, _) = calculate(1000.0, 1400.0, K, K, SCALE, Winner::P1);
+        let (expected_win, _) = calculate(1400.0, 1000.0, K, K, SCALE, Winner::P1);
+        assert!(upset - 1000.0 > expected_win - 1400.0);
+        assert!((upset - 1000.0 - K * 10.0 / 11.0).abs() < 1e-9);
+
+        let (a, b) = calculate(1000.0, 1000.0, 40.0, 10.0, SCALE, Winner::Score(0.75));
+        assert_eq!((a, b), (1010.0, 997.5));
+    }
 }
@@ -0,0 +1,194 @@
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, FromRequestParts, Query},
+    http::{request::Parts, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{error::Error, fmt};
+
+/// Every way a scheduler or API operation can fail.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SchedulerError {
+    /// The thing being asked about does not exist.
+    NotFound(String),
+    /// The scheduler is not in a state where this operation makes sense.
+    InvalidState(String),
+    /// The operation clashes with something that already happened.
+    Conflict(String),
+    /// The caller could not be identified.
+    Unauthorized(String),
+    /// The caller is known but not allowed to do this.
+    Forbidden(String),
+    /// The request itself is malformed.
+    Validation(String),
+    /// Something went wrong on our side.
+    Internal(String),
+}
+
+impl SchedulerError {
+    /// Stable machine-readable name of the variant, used in JSON error bodies.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SchedulerError::NotFound(_) => "not_found",
+            SchedulerError::InvalidState(_) => "invalid_state",
+            SchedulerError::Conflict(_) => "conflict",
+            SchedulerError::Unauthorized(_) => "unauthorized",
+            SchedulerError::Forbidden(_) => "forbidden",
+            SchedulerError::Validation(_) => "validation",
+            SchedulerError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SchedulerError::NotFound(_) => StatusCode::NOT_FOUND,
+            SchedulerError::InvalidState(_) => StatusCode::CONFLICT,
+            SchedulerError::Conflict(_) => StatusCode::CONFLICT,
+            SchedulerError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            SchedulerError::Forbidden(_) => StatusCode::FORBIDDEN,
+            SchedulerError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            SchedulerError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SchedulerError::NotFound(msg)
+            | SchedulerError::InvalidState(msg)
+            | SchedulerError::Conflict(msg)
+            | SchedulerError::Unauthorized(msg)
+            | SchedulerError::Forbidden(msg)
+            | SchedulerError::Validation(msg)
+            | SchedulerError::Internal(msg) => msg,
+        }
+    }
+}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl Error for SchedulerError {}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: &'static str,
+    message: &'a str,
+}
+
+impl IntoResponse for SchedulerError {
+    fn into_response(self) -> Response {
+        if let SchedulerError::Internal(msg) = &self {
+            tracing::error!("{}", msg);
+        }
+        let body = ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message: self.message(),
+            },
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+/// `Json` extractor whose rejections use the same error body as everything else.
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = SchedulerError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(JsonBody(value)),
+            Err(rejection) => Err(SchedulerError::Validation(rejection.body_text())),
+        }
+    }
+}
+
+/// `Query` extractor whose rejections use the same error body as everything else.
+pub struct QueryParams<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for QueryParams<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = SchedulerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(value)) => Ok(QueryParams(value)),
+            Err(rejection) => Err(SchedulerError::Validation(rejection.body_text())),
+        }
+    }
+}
+
+pub async fn not_found() -> SchedulerError {
+    SchedulerError::NotFound("no such route".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    #[timeout(1000)]
+    async fn test_error_response_body() {
+        let err = SchedulerError::Conflict("match has already been judged".to_string());
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": {
+                    "code": "conflict",
+                    "message": "match has already been judged",
+                }
+            })
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_status_codes() {
+        let msg = String::new;
+        assert_eq!(
+            SchedulerError::NotFound(msg()).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            SchedulerError::InvalidState(msg()).status(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            SchedulerError::Unauthorized(msg()).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            SchedulerError::Validation(msg()).status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
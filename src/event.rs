@@ -0,0 +1,533 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    http::request::Parts,
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    error::SchedulerError,
+    pairing::PairingKind,
+    rating::{self, EloConfig, EngineKind, Glicko2Config, RatingEngine, RatingMode},
+    scheduler::{SchedulerOptions, SchedulerState, States},
+    storage::Storage,
+    votelog,
+};
+
+/// The event served by the routes that are not under `/events/{id}`.
+pub const DEFAULT_EVENT: &str = "default";
+
+/// How a single event is run. Fixed once the event has been created.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventConfig {
+    pub name: String,
+    pub engine: EngineKind,
+    pub rating_mode: RatingMode,
+    pub pairing: PairingKind,
+    /// How many times a single judge may be shown the same item, 0 for no limit.
+    pub max_item_views: u32,
+    pub lease_secs: u64,
+    /// Closes a rating period this often while the event is running. Only used
+    /// in period mode; periods can always be closed by hand.
+    pub rating_period_secs: Option<u64>,
+    /// Rounds of seed matches `POST /scheduler_start` creates when it is not
+    /// told how many.
+    pub seed_rounds: usize,
+    /// Only used by the Elo engine.
+    pub elo: EloConfig,
+    /// Only used by the Glicko-2 engine.
+    pub glicko2: Glicko2Config,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        let options = SchedulerOptions::default();
+        EventConfig {
+            name: String::new(),
+            engine: EngineKind::Glicko2,
+            rating_mode: options.rating_mode,
+            pairing: options.pairing,
+            max_item_views: options.max_item_views.unwrap_or(0),
+            lease_secs: options.lease_timeout.as_secs(),
+            rating_period_secs: None,
+            seed_rounds: options.seed_rounds,
+            elo: EloConfig::default(),
+            glicko2: Glicko2Config::default(),
+        }
+    }
+}
+
+impl EventConfig {
+    pub fn options(&self) -> SchedulerOptions {
+        SchedulerOptions {
+            rating_mode: self.rating_mode,
+            pairing: self.pairing,
+            max_item_views: match self.max_item_views {
+                0 => None,
+                max => Some(max),
+            },
+            lease_timeout: Duration::from_secs(self.lease_secs),
+            seed_rounds: self.seed_rounds,
+            seed: None,
+        }
+    }
+
+    pub fn engine(&self) -> Arc<dyn RatingEngine> {
+        rating::engine_with(self.engine, self.elo, self.glicko2)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.lease_secs == 0 {
+            return Err("lease_secs must be at least 1".to_string());
+        }
+        if self.rating_period_secs == Some(0) {
+            return Err("rating_period_secs must be at least 1".to_string());
+        }
+        if self.seed_rounds == 0 {
+            return Err("seed_rounds must be at least 1".to_string());
+        }
+        self.elo.validate()?;
+        self.glicko2.validate()
+    }
+}
+
+/// One competition: its configuration and everything judged in it so far.
+#[derive(Clone)]
+pub struct Event {
+    pub id: String,
+    pub config: EventConfig,
+    pub state: SchedulerState,
+    last_period_close_ms: Arc<AtomicU64>,
+}
+
+#[derive(Serialize)]
+pub struct EventSummary {
+    pub id: String,
+    pub config: EventConfig,
+    pub state: States,
+}
+
+impl Event {
+    pub fn summary(&self) -> EventSummary {
+        EventSummary {
+            id: self.id.clone(),
+            config: self.config.clone(),
+            state: self.state.get_state(),
+        }
+    }
+}
+
+/// Every event hosted by this process. Each event lives in its own SQLite
+/// file, `<dir>/<id>.db`, which also records the event's configuration.
+#[derive(Clone)]
+pub struct Events {
+    dir: Option<PathBuf>,
+    defaults: EventConfig,
+    events: Arc<DashMap<String, Event>>,
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Copies `overrides` into `config`. Nested objects are merged key by key, so
+/// overriding one field of `elo` keeps the rest of it.
+fn merge(
+    config: &mut serde_json::Map<String, serde_json::Value>,
+    overrides: serde_json::Map<String, serde_json::Value>,
+) {
+    for (key, value) in overrides {
+        match (config.get_mut(&key), value) {
+            (Some(serde_json::Value::Object(base)), serde_json::Value::Object(value)) => {
+                merge(base, value)
+            }
+            (_, value) => {
+                config.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Stores `config` and loads the event's state from `storage`.
+fn build(id: &str, config: EventConfig, storage: Storage) -> rusqlite::Result<Event> {
+    storage.save_event_config(&config)?;
+    let state = SchedulerState::load(config.engine(), config.options(), Arc::new(storage))?;
+    Ok(Event {
+        id: id.to_string(),
+        config,
+        state,
+        last_period_close_ms: Arc::new(AtomicU64::new(votelog::now_ms())),
+    })
+}
+
+impl Events {
+    /// Loads every event stored in `dir`, creating the directory and the
+    /// default event if needed. `defaults` is the configuration given to new
+    /// events unless they override it.
+    pub fn open(dir: PathBuf, defaults: EventConfig) -> Result<Events, Box<dyn Error>> {
+        fs::create_dir_all(&dir)?;
+        let events = Events {
+            dir: Some(dir.clone()),
+            defaults,
+            events: Arc::from(DashMap::new()),
+        };
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "db"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !valid_id(id) {
+                continue;
+            }
+            let storage = Storage::open(&path)?;
+            let config = match storage.load_event_config()? {
+                Some(config) => config,
+                None => events.defaults_for(id),
+            };
+            events.insert(id, config, storage)?;
+            tracing::info!("loaded event {} from {}", id, path.display());
+        }
+
+        if !events.events.contains_key(DEFAULT_EVENT) {
+            let config = events.defaults_for(DEFAULT_EVENT);
+            events.insert(DEFAULT_EVENT, config, events.storage_for(DEFAULT_EVENT)?)?;
+        }
+        Ok(events)
+    }
+
+    /// Events that only live as long as the process, for tests.
+    #[cfg(test)]
+    pub fn in_memory(defaults: EventConfig) -> Events {
+        let events = Events {
+            dir: None,
+            defaults,
+            events: Arc::from(DashMap::new()),
+        };
+        let config = events.defaults_for(DEFAULT_EVENT);
+        events
+            .insert(DEFAULT_EVENT, config, Storage::open_in_memory().unwrap())
+            .unwrap();
+        events
+    }
+
+    fn defaults_for(&self, id: &str) -> EventConfig {
+        EventConfig {
+            name: id.to_string(),
+            ..self.defaults.clone()
+        }
+    }
+
+    fn storage_for(&self, id: &str) -> rusqlite::Result<Storage> {
+        match &self.dir {
+            Some(dir) => Storage::open(dir.join(format!("{}.db", id))),
+            // SQLite's name for a private in-memory database
+            None => Storage::open(":memory:"),
+        }
+    }
+
+    fn insert(&self, id: &str, config: EventConfig, storage: Storage) -> rusqlite::Result<Event> {
+        let event = build(id, config, storage)?;
+        self.events.insert(id.to_string(), event.clone());
+        Ok(event)
+    }
+
+    /// Creates a new event. `overrides` is a JSON object holding any subset of
+    /// the [`EventConfig`] fields, nested ones included; the rest come from the
+    /// defaults.
+    pub fn create(
+        &self,
+        id: &str,
+        overrides: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Event, SchedulerError> {
+        if !valid_id(id) {
+            return Err(SchedulerError::Validation(
+                "event ids must be 1-64 characters of a-z, 0-9, `-` and `_`".to_string(),
+            ));
+        }
+        // holding the entry keeps a concurrent create of the same id out until
+        // this one has been stored or has failed
+        let slot = match self.events.entry(id.to_string()) {
+            Entry::Occupied(_) => {
+                return Err(SchedulerError::Conflict(format!(
+                    "event {} already exists",
+                    id
+                )))
+            }
+            Entry::Vacant(slot) => slot,
+        };
+
+        let mut config = match serde_json::to_value(self.defaults_for(id)) {
+            Ok(serde_json::Value::Object(config)) => config,
+            _ => unreachable!("EventConfig always serializes to an object"),
+        };
+        merge(&mut config, overrides);
+        let config: EventConfig = serde_json::from_value(serde_json::Value::Object(config))
+            .map_err(|err| SchedulerError::Validation(format!("invalid event config: {}", err)))?;
+        config
+            .validate()
+            .map_err(|err| SchedulerError::Validation(format!("invalid event config: {}", err)))?;
+
+        let internal = |err: rusqlite::Error| {
+            SchedulerError::Internal(format!("failed to create event {}: {}", id, err))
+        };
+        let storage = self.storage_for(id).map_err(internal)?;
+        let event = build(id, config, storage).map_err(internal)?;
+        slot.insert(event.clone());
+        tracing::info!("created event {}", id);
+        Ok(event)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Event, SchedulerError> {
+        self.events
+            .get(id)
+            .map(|e| e.clone())
+            .ok_or_else(|| SchedulerError::NotFound(format!("event {} does not exist", id)))
+    }
+
+    pub fn list(&self) -> Vec<Event> {
+        let mut events: Vec<Event> = self.events.iter().map(|e| e.clone()).collect();
+        events.sort_by(|a, b| a.id.cmp(&b.id));
+        events
+    }
+
+    /// Housekeeping for every event: releases expired leases and closes rating
+    /// periods that are due.
+    pub fn tick(&self) {
+        let now = votelog::now_ms();
+        for event in self.list() {
+            event.state.release_expired_leases();
+
+            let Some(secs) = event.config.rating_period_secs else {
+                continue;
+            };
+            if event.config.rating_mode != RatingMode::Period
+                || now < event.last_period_close_ms.load(Ordering::Relaxed) + secs * 1000
+            {
+                continue;
+            }
+            event.last_period_close_ms.store(now, Ordering::Relaxed);
+            // periods only pass while the event is running
+            if !matches!(event.state.get_state(), States::Init | States::Continuous) {
+                continue;
+            }
+            if let Err(err) = event.state.close_rating_period() {
+                tracing::error!(
+                    "failed to close rating period of event {}: {}",
+                    event.id,
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// The id of the event named by the `event_id` path parameter, or of the
+/// default event on routes without one.
+pub struct EventId(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for EventId
+where
+    S: Send + Sync,
+{
+    type Rejection = SchedulerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let params = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map(|Path(params)| params)
+            .unwrap_or_default();
+        Ok(EventId(
+            params
+                .get("event_id")
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_EVENT.to_string()),
+        ))
+    }
+}
+
+/// The scheduler of the event the request is for, see [`EventId`].
+pub struct EventState(pub SchedulerState);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for EventState
+where
+    Events: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = SchedulerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let EventId(id) = EventId::from_request_parts(parts, state).await?;
+        Ok(EventState(Events::from_ref(state).get(&id)?.state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::scheduler::Item;
+
+    fn overrides(json: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        match json {
+            serde_json::Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_events_are_isolated() {
+        let defaults = EventConfig {
+            elo: EloConfig {
+                k: 24.0,
+                scale: 600.0,
+                ..EloConfig::default()
+            },
+            glicko2: Glicko2Config {
+                tau: 0.9,
+                ..Glicko2Config::default()
+            },
+            ..EventConfig::default()
+        };
+        let events = Events::in_memory(defaults.clone());
+        let track = events
+            .create(
+                "track-a",
+                overrides(serde_json::json!({
+                    "engine": "elo",
+                    "name": "Track A",
+                    "elo": {"initial_rating": 1200, "min_k": 16},
+                    "glicko2": {"initial_rd": 300}
+                })),
+            )
+            .unwrap();
+        assert_eq!(track.config.engine, EngineKind::Elo);
+        // fields the override leaves out keep the server's values
+        assert_eq!(track.config.elo.k, 24.0);
+        assert_eq!(track.config.elo.scale, 600.0);
+        assert_eq!(track.config.elo.min_k, Some(16.0));
+        assert_eq!(track.config.glicko2.initial_rd, 300.0);
+        assert_eq!(track.config.glicko2.tau, 0.9);
+        assert_eq!(
+            track.config.glicko2.initial_volatility,
+            defaults.glicko2.initial_volatility
+        );
+        assert_eq!(track.state.engine().initial_rating().mu, 1200.0);
+        assert_eq!(track.config.name, "Track A");
+        assert_eq!(track.config.lease_secs, EventConfig::default().lease_secs);
+
+        let default = events.get(DEFAULT_EVENT).unwrap();
+        default.state.add_item(Item::new(
+            "item".to_string(),
+            String::new(),
+            String::new(),
+            default.state.engine().as_ref(),
+        ));
+        assert_eq!(default.state.get_items().len(), 1);
+        assert!(events.get("track-a").unwrap().state.get_items().is_empty());
+
+        let ids: Vec<String> = events.list().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["default", "track-a"]);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_create_validation() {
+        let events = Events::in_memory(EventConfig::default());
+        let empty = serde_json::Map::new;
+        assert!(matches!(
+            events.create("Bad Id", empty()),
+            Err(SchedulerError::Validation(_))
+        ));
+        assert!(matches!(
+            events.create("default", empty()),
+            Err(SchedulerError::Conflict(_))
+        ));
+        assert!(matches!(
+            events.create("x", overrides(serde_json::json!({"engine": "chess"}))),
+            Err(SchedulerError::Validation(_))
+        ));
+        assert!(matches!(
+            events.create("y", overrides(serde_json::json!({"lease": 5}))),
+            Err(SchedulerError::Validation(_))
+        ));
+        for invalid in [
+            serde_json::json!({"elo": {"k": -1}}),
+            serde_json::json!({"glicko2": {"initial_volatility": 0}}),
+            serde_json::json!({"glicko2": {"volatility": 0.06}}),
+        ] {
+            assert!(matches!(
+                events.create("z", overrides(invalid)),
+                Err(SchedulerError::Validation(_))
+            ));
+        }
+        assert!(matches!(
+            events.get("missing"),
+            Err(SchedulerError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_concurrent_creates_of_one_id() {
+        let events = Events::in_memory(EventConfig::default());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let events = events.clone();
+                std::thread::spawn(move || events.create("finals", serde_json::Map::new()))
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|err| matches!(err, SchedulerError::Conflict(_))));
+        assert_eq!(events.list().len(), 2);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_events_reload_from_dir() {
+        let dir = std::env::temp_dir().join(format!("ranker-events-{}", uuid::Uuid::new_v4()));
+        let events = Events::open(dir.clone(), EventConfig::default()).unwrap();
+        events
+            .create(
+                "finals",
+                overrides(serde_json::json!({"rating_mode": "period"})),
+            )
+            .unwrap();
+        drop(events);
+
+        let reopened = Events::open(dir.clone(), EventConfig::default()).unwrap();
+        let finals = reopened.get("finals").unwrap();
+        assert_eq!(finals.config.rating_mode, RatingMode::Period);
+        assert!(reopened.get(DEFAULT_EVENT).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
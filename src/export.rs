@@ -0,0 +1,366 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    error::SchedulerError,
+    leaderboard::{LeaderboardEntry, RankingMethod},
+    scheduler::{Judge, MatchWinner, SchedulerState},
+    votelog,
+};
+
+/// One row of the verdict history, with names alongside ids so the file can
+/// be read on its own.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VerdictRow {
+    pub seq: u64,
+    pub timestamp_ms: u64,
+    pub period: u64,
+    pub judge_id: String,
+    pub judge_email: String,
+    pub match_id: String,
+    pub item_a: String,
+    pub item_a_name: String,
+    pub item_b: String,
+    pub item_b_name: String,
+    pub winner: MatchWinner,
+    /// What item A scored, from 1 for a clear win down to 0.
+    pub score_a: f64,
+    pub amends: Option<u64>,
+    /// Set when a later amendment replaced this verdict, so it no longer counts.
+    pub superseded: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JudgeStats {
+    pub judge_id: String,
+    pub email: String,
+    /// Verdicts that currently count, after amendments.
+    pub verdicts: u32,
+    pub amendments: u32,
+    pub ties: u32,
+    pub leans: u32,
+    pub skips: u32,
+    /// How often the judge's verdicts went the way of the final ranking, from
+    /// 0 to 1. Ties and leans count in proportion to their score.
+    pub agreement: Option<f64>,
+    pub first_vote_ms: Option<u64>,
+    pub last_vote_ms: Option<u64>,
+}
+
+pub fn verdicts(state: &SchedulerState) -> Vec<VerdictRow> {
+    let votes = state.get_votes();
+    let effective: HashSet<u64> = votelog::effective(&votes).iter().map(|v| v.seq).collect();
+    let items: HashMap<String, String> = state
+        .get_items()
+        .into_iter()
+        .map(|item| (item.id, item.name))
+        .collect();
+    let judges: HashMap<String, String> = state
+        .get_judges()
+        .into_iter()
+        .map(|judge| (judge.id, judge.email))
+        .collect();
+    let name =
+        |names: &HashMap<String, String>, id: &str| names.get(id).cloned().unwrap_or_default();
+
+    votes
+        .iter()
+        .map(|vote| VerdictRow {
+            seq: vote.seq,
+            timestamp_ms: vote.timestamp_ms,
+            period: vote.period,
+            judge_id: vote.judge_id.clone(),
+            judge_email: name(&judges, &vote.judge_id),
+            match_id: vote.match_id.clone(),
+            item_a: vote.i1.clone(),
+            item_a_name: name(&items, &vote.i1),
+            item_b: vote.i2.clone(),
+            item_b_name: name(&items, &vote.i2),
+            winner: vote.winner,
+            score_a: vote.winner.score_a(),
+            amends: vote.amends,
+            superseded: !effective.contains(&vote.seq),
+        })
+        .collect()
+}
+
+/// Per-judge activity, with agreement measured against `leaderboard`.
+pub fn judge_stats(state: &SchedulerState, leaderboard: &[LeaderboardEntry]) -> Vec<JudgeStats> {
+    let votes = state.get_votes();
+    let rank: HashMap<&str, usize> = leaderboard
+        .iter()
+        .map(|entry| (entry.id.as_str(), entry.rank))
+        .collect();
+    let mut stats: Vec<JudgeStats> = state
+        .get_judges()
+        .into_iter()
+        .map(|Judge { id, email, .. }| JudgeStats {
+            judge_id: id,
+            email,
+            verdicts: 0,
+            amendments: 0,
+            ties: 0,
+            leans: 0,
+            skips: 0,
+            agreement: None,
+            first_vote_ms: None,
+            last_vote_ms: None,
+        })
+        .collect();
+    let index: HashMap<String, usize> = stats
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.judge_id.clone(), i))
+        .collect();
+
+    let mut agreed = vec![0f64; stats.len()];
+    // verdicts between two ranked items, the only ones agreement can be judged on
+    let mut ranked = vec![0u32; stats.len()];
+    for vote in &votes {
+        let Some(s) = index.get(&vote.judge_id).map(|&i| &mut stats[i]) else {
+            continue;
+        };
+        if vote.amends.is_some() {
+            s.amendments += 1;
+        }
+        s.first_vote_ms = Some(
+            s.first_vote_ms
+                .map_or(vote.timestamp_ms, |t| t.min(vote.timestamp_ms)),
+        );
+        s.last_vote_ms = Some(
+            s.last_vote_ms
+                .map_or(vote.timestamp_ms, |t| t.max(vote.timestamp_ms)),
+        );
+    }
+    for vote in votelog::effective(&votes) {
+        let Some(&i) = index.get(&vote.judge_id) else {
+            continue;
+        };
+        let s = &mut stats[i];
+        s.verdicts += 1;
+        match vote.winner {
+            MatchWinner::Tie => s.ties += 1,
+            MatchWinner::LeanA | MatchWinner::LeanB => s.leans += 1,
+            MatchWinner::A | MatchWinner::B => {}
+        }
+        if let (Some(a), Some(b)) = (rank.get(vote.i1.as_str()), rank.get(vote.i2.as_str())) {
+            let score_a = vote.winner.score_a();
+            agreed[i] += if a < b { score_a } else { 1.0 - score_a };
+            ranked[i] += 1;
+        }
+    }
+    for skip in state.get_skips() {
+        if let Some(&i) = index.get(&skip.judge_id) {
+            stats[i].skips += 1;
+        }
+    }
+    for ((s, agreed), ranked) in stats.iter_mut().zip(agreed).zip(ranked) {
+        if ranked > 0 {
+            s.agreement = Some(agreed / ranked as f64);
+        }
+    }
+    stats
+}
+
+pub fn to_csv<T: Serialize>(rows: &[T]) -> Result<String, SchedulerError> {
+    let internal = |err: &dyn std::fmt::Display| {
+        SchedulerError::Internal(format!("failed to write csv: {}", err))
+    };
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row).map_err(|err| internal(&err))?;
+    }
+    let bytes = writer.into_inner().map_err(|err| internal(&err))?;
+    String::from_utf8(bytes).map_err(|err| internal(&err))
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `YYYY-MM-DD HH:MM UTC` for a Unix timestamp in milliseconds.
+fn format_utc(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // days since the epoch to a civil date, after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; color: #222; }
+h1 { margin-bottom: 0.25rem; }
+p.meta { color: #666; margin-top: 0; }
+table { border-collapse: collapse; width: 100%; }
+th, td { padding: 0.4rem 0.6rem; border-bottom: 1px solid #ddd; text-align: left; }
+td.num, th.num { text-align: right; font-variant-numeric: tabular-nums; }
+tr.close td { border-bottom-style: dashed; }
+footer { color: #666; font-size: 0.9rem; margin-top: 1.5rem; }
+";
+
+/// A standalone results page: plain HTML with the styles inlined, so it can
+/// be saved, emailed or hosted anywhere.
+pub fn html_report(
+    title: &str,
+    method: RankingMethod,
+    leaderboard: &[LeaderboardEntry],
+    verdicts: usize,
+    judges: usize,
+    generated_ms: u64,
+) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title} results</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<p class=\"meta\">{verdicts} verdicts from {judges} judges, \
+         ranked by {method}</p>\n<table>\n<thead><tr><th class=\"num\">Rank</th><th>Name</th>\
+         <th>Location</th><th class=\"num\">Rating</th><th class=\"num\">95% interval</th>\
+         <th class=\"num\">W-L-T</th></tr></thead>\n<tbody>\n",
+        title = escape(title),
+    );
+    for entry in leaderboard {
+        let _ = writeln!(
+            html,
+            "<tr{}><td class=\"num\">{}</td><td>{}</td><td>{}</td><td class=\"num\">{:.0}</td>\
+             <td class=\"num\">{:.0}&ndash;{:.0}</td><td class=\"num\">{}-{}-{}</td></tr>",
+            if entry.indistinguishable_from_next {
+                " class=\"close\""
+            } else {
+                ""
+            },
+            entry.rank,
+            escape(&entry.name),
+            escape(&entry.location),
+            entry.rating,
+            entry.low,
+            entry.high,
+            entry.wins,
+            entry.losses,
+            entry.ties,
+        );
+    }
+    let _ = write!(
+        html,
+        "</tbody>\n</table>\n<footer>A dashed line below an entry means it cannot be told \
+         apart from the next one at 95% confidence. Generated {}.</footer>\n</body>\n</html>\n",
+        format_utc(generated_ms)
+    );
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::{
+        rating::{engine_for, EngineKind},
+        scheduler::{Item, SchedulerOptions, SkipReason},
+    };
+
+    fn event() -> (SchedulerState, Judge, Judge) {
+        let state =
+            SchedulerState::new(engine_for(EngineKind::Glicko2), SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        for name in ["<b>Bold</b>", "Plain"] {
+            state.add_item(Item::new(
+                name.to_string(),
+                "Hall, table 3".to_string(),
+                String::new(),
+                state.engine().as_ref(),
+            ));
+        }
+        state.seed_start(2).unwrap();
+        (state, judge, other)
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_verdicts_and_judge_stats() {
+        let (state, judge, other) = event();
+        let first = state.give_judge_next_match(&judge).unwrap();
+        state
+            .judge_match(&judge, &first.match_pair_id, MatchWinner::B, false)
+            .unwrap();
+        state
+            .judge_match(&judge, &first.match_pair_id, MatchWinner::A, true)
+            .unwrap();
+        let second = state.give_judge_next_match(&other).unwrap();
+        state
+            .skip_match(&other, &second.match_pair_id, SkipReason::Unfamiliar, None)
+            .unwrap();
+
+        let rows = verdicts(&state);
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].superseded && !rows[1].superseded);
+        assert_eq!(rows[1].amends, Some(rows[0].seq));
+        assert_eq!(rows[1].judge_email, "judge@example.com");
+        assert!(!rows[1].item_a_name.is_empty());
+
+        let board = state.leaderboard(RankingMethod::Live, false);
+        let stats = judge_stats(&state, &board);
+        assert_eq!(stats[0].verdicts, 1);
+        assert_eq!(stats[0].amendments, 1);
+        // the only verdict counted decided the ranking
+        assert_eq!(stats[0].agreement, Some(1.0));
+        assert_eq!((stats[1].verdicts, stats[1].skips), (0, 1));
+        assert_eq!(stats[1].agreement, None);
+
+        let csv = to_csv(&rows).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("seq,timestamp_ms,period,judge_id"));
+        assert_eq!(lines.count(), 2);
+        assert!(to_csv(&board).unwrap().contains("\"Hall, table 3\""));
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_html_report() {
+        let (state, _, _) = event();
+        let board = state.leaderboard(RankingMethod::Live, false);
+        let html = html_report("Hack & Tell", RankingMethod::Live, &board, 0, 2, 0);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>Hack &amp; Tell</h1>"));
+        assert!(html.contains("&lt;b&gt;Bold&lt;/b&gt;"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("1970-01-01 00:00 UTC"));
+        assert_eq!(format_utc(1_709_210_096_000), "2024-02-29 12:34 UTC");
+    }
+}
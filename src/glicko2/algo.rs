@@ -0,0 +1,439 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Default system constant, which limits how fast volatility can change.
+pub const TAU: f64 = 0.5;
+const FACTOR: f64 = 173.7178;
+/// Default tolerance the volatility is solved to.
+pub const EPSILON: f64 = 0.000001;
+
+pub const INITIAL_RATING: f64 = 1500.0;
+pub const INITIAL_RD: f64 = 350.0;
+/// The volatility Glickman recommends starting players at.
+pub const INITIAL_VOLATILITY: f64 = 0.06;
+
+/// Most steps the volatility solver takes, both to bracket the root and to
+/// narrow it down, before giving up.
+const MAX_ITERATIONS: usize = 100;
+
+/// The new volatility could not be solved for within [`MAX_ITERATIONS`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NotConverged;
+
+impl fmt::Display for NotConverged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Glicko-2 volatility did not converge within {} iterations",
+            MAX_ITERATIONS
+        )
+    }
+}
+
+/// Constants shared by every rating in a pool.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Constants {
+    pub tau: f64,
+    pub epsilon: f64,
+}
+
+impl Default for Constants {
+    fn default() -> Self {
+        Constants {
+            tau: TAU,
+            epsilon: EPSILON,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Glicko1 {
+    pub rating: f64,
+    pub sigma: f64,
+    pub rd: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Glicko2 {
+    pub mu: f64,
+    pub sigma: f64,
+    pub phi: f64,
+}
+
+impl Glicko2 {
+    #[allow(dead_code)]
+    pub fn from_glicko1(g1: &Glicko1) -> Self {
+        Glicko2 {
+            mu: (g1.rating - 1500.0) / FACTOR,
+            sigma: g1.sigma,
+            phi: g1.rd / FACTOR,
+        }
+    }
+
+    pub fn from_glicko1_vars(rating: f64, sigma: f64, rd: f64) -> Self {
+        Glicko2 {
+            mu: (rating - 1500.0) / FACTOR,
+            sigma,
+            phi: rd / FACTOR,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::from_glicko1_vars(INITIAL_RATING, INITIAL_VOLATILITY, INITIAL_RD)
+    }
+
+    /// Steps 6 to 8: the new rating deviation and rating given the new volatility.
+    fn update_glicko2_vars(
+        &mut self,
+        v: f64,
+        g_opponents: &[&Glicko2],
+        scores: &[f64],
+        sigma_prime: f64,
+    ) {
+        let phi_star = get_new_rating_dev(self, sigma_prime);
+        let phi = 1f64 / (1f64 / phi_star.powi(2) + 1f64 / v).sqrt();
+        let mu = self.mu + phi.powi(2) * score_sum(self, g_opponents, scores);
+
+        self.mu = mu;
+        self.sigma = sigma_prime;
+        self.phi = phi;
+    }
+
+    /// Step 6 of the Glicko-2 algorithm for a player who did not compete in a
+    /// rating period: only the rating deviation grows.
+    pub fn skip_period(&mut self) {
+        self.phi = get_new_rating_dev(self, self.sigma);
+    }
+
+    /// Applies one rating period. A period without games only grows the rating
+    /// deviation, as the paper prescribes. If the volatility cannot be solved
+    /// for, the rating is left untouched.
+    pub fn process_matches(
+        &mut self,
+        g_opponents: &[&Glicko2],
+        scores: &[f64],
+        constants: &Constants,
+    ) -> Result<(), NotConverged> {
+        assert_eq!(g_opponents.len(), scores.len());
+        if g_opponents.is_empty() {
+            self.skip_period();
+            return Ok(());
+        }
+
+        let v = compute_v(self, g_opponents);
+        let delta = compute_delta(self, g_opponents, scores);
+        let sigma_prime = sigma_by_illinois(self, delta, v, constants)?;
+        self.update_glicko2_vars(v, g_opponents, scores, sigma_prime);
+        Ok(())
+    }
+
+    /// Like [`Glicko2::process_matches`], but keeps the current volatility
+    /// instead of solving for a new one.
+    pub fn process_matches_keeping_volatility(&mut self, g_opponents: &[&Glicko2], scores: &[f64]) {
+        assert_eq!(g_opponents.len(), scores.len());
+        if g_opponents.is_empty() {
+            self.skip_period();
+            return;
+        }
+
+        let v = compute_v(self, g_opponents);
+        self.update_glicko2_vars(v, g_opponents, scores, self.sigma);
+    }
+}
+
+impl Default for Glicko2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Glicko1 {
+    pub fn from_glicko2(g2: &Glicko2) -> Self {
+        Glicko1 {
+            rating: g2.mu * FACTOR + 1500.0,
+            sigma: g2.sigma,
+            rd: g2.phi * FACTOR,
+        }
+    }
+}
+
+fn e(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / std::f64::consts::PI.powi(2)).sqrt()
+}
+
+/// Estimated variance of the rating based on game outcomes. Only defined when
+/// there is at least one opponent.
+fn compute_v(g_cur: &Glicko2, g_opponents: &[&Glicko2]) -> f64 {
+    debug_assert!(!g_opponents.is_empty());
+    let sum: f64 = g_opponents
+        .iter()
+        .map(|g_op| {
+            g(g_op.phi).powi(2)
+                * e(g_cur.mu, g_op.mu, g_op.phi)
+                * (1f64 - e(g_cur.mu, g_op.mu, g_op.phi))
+        })
+        .sum();
+    1f64 / sum
+}
+
+/// How much better or worse than expected the player scored, weighted by how
+/// much each opponent's rating can be trusted.
+fn score_sum(g_cur: &Glicko2, g_opponents: &[&Glicko2], scores: &[f64]) -> f64 {
+    assert_eq!(g_opponents.len(), scores.len());
+    g_opponents
+        .iter()
+        .zip(scores)
+        .map(|(g_op, score)| g(g_op.phi) * (score - e(g_cur.mu, g_op.mu, g_op.phi)))
+        .sum()
+}
+
+fn compute_delta(g_cur: &Glicko2, g_opponents: &[&Glicko2], scores: &[f64]) -> f64 {
+    compute_v(g_cur, g_opponents) * score_sum(g_cur, g_opponents, scores)
+}
+
+/// Step 5: the new volatility, found with the Illinois variant of regula falsi.
+fn sigma_by_illinois(
+    g_cur: &Glicko2,
+    delta: f64,
+    v: f64,
+    constants: &Constants,
+) -> Result<f64, NotConverged> {
+    let Constants { tau, epsilon } = *constants;
+    let a = g_cur.sigma.powi(2).ln();
+    let phi_sq_plus_v = g_cur.phi.powi(2) + v;
+    let f = |x: f64| -> f64 {
+        let ex = x.exp();
+        let t1 = ex * (delta.powi(2) - phi_sq_plus_v - ex) / (2f64 * (phi_sq_plus_v + ex).powi(2));
+        let t2 = (x - a) / tau.powi(2);
+        t1 - t2
+    };
+
+    let mut a_prime = a;
+    let mut b_prime = if delta.powi(2) > phi_sq_plus_v {
+        (delta.powi(2) - phi_sq_plus_v).ln()
+    } else {
+        let k = (1..=MAX_ITERATIONS)
+            .find(|&k| f(a - k as f64 * tau) >= 0.0)
+            .ok_or(NotConverged)?;
+        a - k as f64 * tau
+    };
+
+    let mut f_a = f(a_prime);
+    let mut f_b = f(b_prime);
+    for _ in 0..MAX_ITERATIONS {
+        if (b_prime - a_prime).abs() <= epsilon {
+            let sigma = (a_prime / 2f64).exp();
+            return if sigma.is_finite() && sigma > 0.0 {
+                Ok(sigma)
+            } else {
+                Err(NotConverged)
+            };
+        }
+        let c = a_prime + (a_prime - b_prime) * f_a / (f_b - f_a);
+        let f_c = f(c);
+        if !c.is_finite() || !f_c.is_finite() {
+            return Err(NotConverged);
+        }
+        if f_c * f_b <= 0f64 {
+            a_prime = b_prime;
+            f_a = f_b;
+        } else {
+            f_a /= 2f64;
+        }
+        b_prime = c;
+        f_b = f_c;
+    }
+    Err(NotConverged)
+}
+
+fn get_new_rating_dev(g_cur: &Glicko2, sigma_prime: f64) -> f64 {
+    (g_cur.phi.powi(2) + sigma_prime.powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    #[timeout(100)]
+    fn test_example_all() {
+        let mut p1 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1500f64,
+            sigma: 0.06f64,
+            rd: 200f64,
+        });
+
+        let o1 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1400f64,
+            sigma: 0.06f64,
+            rd: 30f64,
+        });
+
+        let o2 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1550f64,
+            sigma: 0.06f64,
+            rd: 100f64,
+        });
+
+        let o3 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1700f64,
+            sigma: 0.06f64,
+            rd: 300f64,
+        });
+
+        let scores: Vec<f64> = vec![1f64, 0f64, 0f64];
+        let opps = vec![&o1, &o2, &o3];
+
+        p1.process_matches(&opps, &scores, &Constants::default())
+            .unwrap();
+        let as_g1 = Glicko1::from_glicko2(&p1);
+
+        // the worked example from Glickman's paper, which uses TAU = 0.5
+        assert!((as_g1.rating - 1464.06).abs() < 0.01);
+        assert!((as_g1.sigma - 0.05999).abs() < 0.00001);
+        assert!((as_g1.rd - 151.52).abs() < 0.01);
+        let sigma = sigma_by_illinois(&start(), -0.4834, 1.7785, &Constants::default());
+        assert!((sigma.unwrap() - 0.05999).abs() < 0.00001);
+    }
+
+    fn start() -> Glicko2 {
+        Glicko2::from_glicko1_vars(1500.0, 0.06, 200.0)
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_empty_period() {
+        let mut p = start();
+        p.process_matches(&[], &[], &Constants::default()).unwrap();
+        assert_eq!(p.mu, start().mu);
+        assert_eq!(p.sigma, start().sigma);
+        assert!((p.phi - (start().phi.powi(2) + 0.06f64.powi(2)).sqrt()).abs() < 1e-12);
+
+        let mut kept = start();
+        kept.process_matches_keeping_volatility(&[], &[]);
+        assert_eq!(kept, p);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_keeping_volatility() {
+        let o = Glicko2::from_glicko1_vars(1400.0, 0.06, 30.0);
+        let mut solved = start();
+        solved
+            .process_matches(&[&o], &[1.0], &Constants::default())
+            .unwrap();
+        let mut kept = start();
+        kept.process_matches_keeping_volatility(&[&o], &[1.0]);
+        assert_eq!(kept.sigma, start().sigma);
+        assert!((kept.mu - solved.mu).abs() < 1e-3);
+        assert!(kept.mu > start().mu);
+
+        let broken = Glicko2 {
+            sigma: f64::NAN,
+            ..start()
+        };
+        assert_eq!(
+            sigma_by_illinois(&broken, -0.4834, 1.7785, &Constants::default()),
+            Err(NotConverged)
+        );
+        let mut unchanged = broken;
+        assert_eq!(
+            unchanged.process_matches(&[&o], &[1.0], &Constants::default()),
+            Err(NotConverged)
+        );
+        assert_eq!(unchanged.mu, broken.mu);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_random_periods() {
+        let mut rng = StdRng::seed_from_u64(22);
+        let glicko = |rng: &mut StdRng| {
+            Glicko2::from_glicko1_vars(
+                rng.gen_range(500.0..2500.0),
+                rng.gen_range(0.01..0.2),
+                rng.gen_range(20.0..350.0),
+            )
+        };
+        for _ in 0..2000 {
+            let me = glicko(&mut rng);
+            let opponents: Vec<Glicko2> = (0..rng.gen_range(1..12))
+                .map(|_| glicko(&mut rng))
+                .collect();
+            let opponents: Vec<&Glicko2> = opponents.iter().collect();
+            let score = [0.0, 0.25, 0.5, 0.75, 1.0][rng.gen_range(0..5)];
+            let scores = vec![score; opponents.len()];
+
+            let mut next = me;
+            next.process_matches(&opponents, &scores, &Constants::default())
+                .unwrap();
+            assert!(next.mu.is_finite() && next.phi.is_finite());
+            assert!(next.sigma > 0.0 && next.sigma < 1.0);
+            // games always add information
+            assert!(next.phi < (me.phi.powi(2) + next.sigma.powi(2)).sqrt());
+            assert!(next.phi > 0.0);
+            // never moving the wrong way after winning or losing everything
+            if score == 1.0 {
+                assert!(next.mu > me.mu);
+            } else if score == 0.0 {
+                assert!(next.mu < me.mu);
+            }
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_g() {
+        let g_val = g(0.5);
+        assert!((g_val - 0.96404).abs() < 0.001)
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_e() {
+        let e_val = e(0.6, 0.5, 0.5);
+        assert!((e_val - 0.52408).abs() < 0.001)
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_compute_delta() {
+        let p1: Glicko2 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1500f64,
+            sigma: 0.06f64,
+            rd: 200f64,
+        });
+
+        let o1: Glicko2 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1400f64,
+            sigma: 0.06f64,
+            rd: 30f64,
+        });
+
+        let o2: Glicko2 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1550f64,
+            sigma: 0.06f64,
+            rd: 100f64,
+        });
+
+        let o3: Glicko2 = Glicko2::from_glicko1(&Glicko1 {
+            rating: 1700f64,
+            sigma: 0.06f64,
+            rd: 300f64,
+        });
+
+        let scores: Vec<f64> = vec![1f64, 0f64, 0f64];
+        let opps = vec![&o1, &o2, &o3];
+
+        let delta = compute_delta(&p1, &opps, &scores);
+        assert!((delta + 0.483933260).abs() < 0.001);
+        assert!((compute_v(&p1, &opps) - 1.7785).abs() < 0.001);
+    }
+}
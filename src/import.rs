@@ -0,0 +1,502 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    error::SchedulerError,
+    glicko2::algo::Glicko2,
+    scheduler::{name_key, valid_email, Item, Judge, MatchWinner, SchedulerState},
+    votelog::Vote,
+};
+
+/// Column names accepted for each field, compared case-insensitively. The
+/// longer names are the ones used by Devpost's project export.
+const ITEM_NAME: &[&str] = &["name", "title", "project title", "submission title"];
+const ITEM_LOCATION: &[&str] = &["location", "table", "table number"];
+const ITEM_DESCRIPTION: &[&str] = &["description", "tagline", "about the project"];
+const JUDGE_EMAIL: &[&str] = &["email", "email address", "judge email"];
+
+/// How records are laid out in an imported or exported file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// A header row followed by one record per row.
+    Csv,
+    /// An array of objects.
+    #[default]
+    Json,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            other => Err(format!("unknown import format `{}`", other)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Csv => write!(f, "csv"),
+            Format::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    Invalid,
+    Duplicate,
+}
+
+/// A record that was left out, and why.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Rejected {
+    /// 1-based position of the record, not counting the CSV header.
+    pub row: usize,
+    pub problem: Problem,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportReport<T> {
+    /// Nothing was added; `imported` lists what would have been.
+    pub dry_run: bool,
+    pub rows: usize,
+    pub imported: Vec<T>,
+    pub rejected: Vec<Rejected>,
+}
+
+impl<T> ImportReport<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> ImportReport<U> {
+        ImportReport {
+            dry_run: self.dry_run,
+            rows: self.rows,
+            imported: self.imported.into_iter().map(f).collect(),
+            rejected: self.rejected,
+        }
+    }
+}
+
+/// One record, keyed by lowercased column name.
+type Row = HashMap<String, String>;
+
+fn parse(format: Format, data: &str) -> Result<Vec<Row>, SchedulerError> {
+    let invalid = |err: String| SchedulerError::Validation(format!("invalid {}: {}", format, err));
+    match format {
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .from_reader(data.as_bytes());
+            let headers: Vec<String> = reader
+                .headers()
+                .map_err(|err| invalid(err.to_string()))?
+                .iter()
+                .map(|h| h.to_lowercase())
+                .collect();
+            reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(|err| invalid(err.to_string()))?;
+                    Ok(headers
+                        .iter()
+                        .cloned()
+                        .zip(record.iter().map(String::from))
+                        .collect())
+                })
+                .collect()
+        }
+        Format::Json => {
+            let records: Vec<serde_json::Map<String, serde_json::Value>> =
+                serde_json::from_str(data).map_err(|err| invalid(err.to_string()))?;
+            Ok(records
+                .into_iter()
+                .map(|record| {
+                    record
+                        .into_iter()
+                        .map(|(key, value)| {
+                            let value = match value {
+                                serde_json::Value::String(s) => s.trim().to_string(),
+                                serde_json::Value::Null => String::new(),
+                                other => other.to_string(),
+                            };
+                            (key.to_lowercase(), value)
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+    }
+}
+
+fn field(row: &Row, aliases: &[&str]) -> String {
+    aliases
+        .iter()
+        .find_map(|alias| row.get(*alias).filter(|value| !value.is_empty()))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn require_column(rows: &[Row], aliases: &[&str]) -> Result<(), SchedulerError> {
+    if rows.is_empty()
+        || rows
+            .iter()
+            .any(|row| aliases.iter().any(|a| row.contains_key(*a)))
+    {
+        return Ok(());
+    }
+    Err(SchedulerError::Validation(format!(
+        "no column named any of {}",
+        aliases.join(", ")
+    )))
+}
+
+/// Sorts records into new ones and rejected ones. `key` returns the
+/// duplicate-detection key of a valid record, or why the record is invalid.
+fn classify<T>(
+    rows: Vec<Row>,
+    dry_run: bool,
+    existing: HashSet<String>,
+    mut key: impl FnMut(&Row) -> Result<String, String>,
+    mut build: impl FnMut(Row) -> T,
+) -> ImportReport<T> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut report = ImportReport {
+        dry_run,
+        rows: rows.len(),
+        imported: vec![],
+        rejected: vec![],
+    };
+    for (i, row) in rows.into_iter().enumerate() {
+        let mut reject = |problem, message| {
+            report.rejected.push(Rejected {
+                row: i + 1,
+                problem,
+                message,
+            })
+        };
+        match key(&row) {
+            Err(message) => reject(Problem::Invalid, message),
+            Ok(k) if existing.contains(&k) => {
+                reject(Problem::Duplicate, format!("{} already exists", k))
+            }
+            Ok(k) if seen.contains(&k) => {
+                reject(Problem::Duplicate, format!("{} appears earlier", k))
+            }
+            Ok(k) => {
+                seen.insert(k);
+                report.imported.push(build(row));
+            }
+        }
+    }
+    report
+}
+
+/// Adds every valid item in `data` whose name is not taken yet, unless
+/// `dry_run` is set.
+pub fn import_items(
+    state: &SchedulerState,
+    format: Format,
+    data: &str,
+    dry_run: bool,
+) -> Result<ImportReport<Item>, SchedulerError> {
+    let rows = parse(format, data)?;
+    require_column(&rows, ITEM_NAME)?;
+    let engine = state.engine();
+    Ok(state.add_unique_items(|existing| {
+        let report = classify(
+            rows,
+            dry_run,
+            existing,
+            |row| match name_key(&field(row, ITEM_NAME)) {
+                name if name.is_empty() => Err("missing name".to_string()),
+                name => Ok(name),
+            },
+            |row| {
+                Item::new(
+                    field(&row, ITEM_NAME),
+                    field(&row, ITEM_LOCATION),
+                    field(&row, ITEM_DESCRIPTION),
+                    engine.as_ref(),
+                )
+            },
+        );
+        let new_items = if dry_run {
+            vec![]
+        } else {
+            report.imported.clone()
+        };
+        (report, new_items)
+    }))
+}
+
+/// Adds every judge in `data` with a valid email that is not taken yet,
+/// unless `dry_run` is set.
+pub fn import_judges(
+    state: &SchedulerState,
+    format: Format,
+    data: &str,
+    dry_run: bool,
+) -> Result<ImportReport<Judge>, SchedulerError> {
+    let rows = parse(format, data)?;
+    require_column(&rows, JUDGE_EMAIL)?;
+    Ok(state.add_unique_judges(|existing| {
+        let report = classify(
+            rows,
+            dry_run,
+            existing,
+            |row| match field(row, JUDGE_EMAIL).to_lowercase() {
+                email if email.is_empty() => Err("missing email".to_string()),
+                email if !valid_email(&email) => Err(format!("invalid email {}", email)),
+                email => Ok(email),
+            },
+            |row| Judge::new(field(&row, JUDGE_EMAIL)),
+        );
+        let new_judges = if dry_run {
+            vec![]
+        } else {
+            report.imported.clone()
+        };
+        (report, new_judges)
+    }))
+}
+
+/// A verdict as found in `GET /votes` or the verdict export.
+#[derive(Deserialize)]
+struct LoggedVerdict {
+    seq: u64,
+    #[serde(default)]
+    judge_id: String,
+    #[serde(default)]
+    match_id: String,
+    #[serde(alias = "item_a")]
+    i1: String,
+    #[serde(alias = "item_b")]
+    i2: String,
+    winner: MatchWinner,
+    #[serde(default)]
+    timestamp_ms: u64,
+    #[serde(default)]
+    period: u64,
+    #[serde(default)]
+    amends: Option<u64>,
+}
+
+/// Reads a vote log saved from `GET /votes` or `GET /export/votes`, in either
+/// format. The ratings recorded with each vote are not kept; replaying the
+/// log recomputes them.
+pub fn parse_votes(format: Format, data: &str) -> Result<Vec<Vote>, SchedulerError> {
+    let invalid = |err: String| SchedulerError::Validation(format!("invalid {}: {}", format, err));
+    let verdicts: Vec<LoggedVerdict> = match format {
+        Format::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|err| invalid(err.to_string()))?,
+        Format::Json => serde_json::from_str(data).map_err(|err| invalid(err.to_string()))?,
+    };
+    Ok(verdicts
+        .into_iter()
+        .map(|v| Vote {
+            seq: v.seq,
+            judge_id: v.judge_id,
+            match_id: v.match_id,
+            i1: v.i1,
+            i2: v.i2,
+            winner: v.winner,
+            timestamp_ms: v.timestamp_ms,
+            period: v.period,
+            pre_i1: Glicko2::new(),
+            pre_i2: Glicko2::new(),
+            post_i1: Glicko2::new(),
+            post_i2: Glicko2::new(),
+            amends: v.amends,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::{
+        rating::{engine_for, EngineKind},
+        scheduler::SchedulerOptions,
+    };
+
+    fn state() -> SchedulerState {
+        SchedulerState::new(engine_for(EngineKind::Glicko2), SchedulerOptions::default())
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_import_devpost_csv() {
+        let state = state();
+        state.add_item(Item::new(
+            "Existing".to_string(),
+            String::new(),
+            String::new(),
+            state.engine().as_ref(),
+        ));
+        let csv = "\
+Project Title,Submission Url,Table Number,About The Project
+Hack One,https://devpost.com/1,12,Does things
+,https://devpost.com/2,13,No title
+  hack   ONE ,https://devpost.com/3,14,Same again
+existing,https://devpost.com/4,15,Already there
+Hack Two,https://devpost.com/5,16,\"Quoted, with a comma\"
+";
+        let dry = import_items(&state, Format::Csv, csv, true).unwrap();
+        assert!(dry.dry_run);
+        assert_eq!(dry.rows, 5);
+        assert_eq!(dry.imported.len(), 2);
+        assert_eq!(state.get_items().len(), 1);
+        let problems: Vec<(usize, Problem)> =
+            dry.rejected.iter().map(|r| (r.row, r.problem)).collect();
+        assert_eq!(
+            problems,
+            vec![
+                (2, Problem::Invalid),
+                (3, Problem::Duplicate),
+                (4, Problem::Duplicate)
+            ]
+        );
+
+        let report = import_items(&state, Format::Csv, csv, false).unwrap();
+        assert_eq!(report.rejected, dry.rejected);
+        let items = state.get_items();
+        assert_eq!(items.len(), 3);
+        let two = items.iter().find(|i| i.name == "Hack Two").unwrap();
+        assert_eq!(two.location, "16");
+        assert_eq!(two.description, "Quoted, with a comma");
+
+        // importing the same file again adds nothing
+        let again = import_items(&state, Format::Csv, csv, false).unwrap();
+        assert!(again.imported.is_empty());
+        assert_eq!(state.get_items().len(), 3);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_overlapping_imports() {
+        let state = state();
+        let items = r#"[{"name": "Shared"}, {"name": "Only A"}]"#;
+        let other_items = r#"[{"name": "shared"}, {"name": "Only B"}]"#;
+        let judges = "email\nshared@example.com\na@example.com\n";
+        let other_judges = "email\nSHARED@example.com\nb@example.com\n";
+        let handles: Vec<_> = [(items, judges), (other_items, other_judges)]
+            .into_iter()
+            .map(|(items, judges)| {
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    let items = import_items(&state, Format::Json, items, false).unwrap();
+                    let judges = import_judges(&state, Format::Csv, judges, false).unwrap();
+                    (items.imported.len(), judges.imported.len())
+                })
+            })
+            .collect();
+        let imported: Vec<(usize, usize)> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // whichever import came second found the shared record taken
+        assert_eq!(imported.iter().map(|(i, _)| i).sum::<usize>(), 3);
+        assert_eq!(imported.iter().map(|(_, j)| j).sum::<usize>(), 3);
+        assert_eq!(state.get_items().len(), 3);
+        assert_eq!(state.get_judges().len(), 3);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_import_judges_json() {
+        let state = state();
+        state.add_judge(Judge::new("taken@example.com".to_string()));
+        let json = r#"[
+            {"email": "a@example.com"},
+            {"Email": "A@Example.com"},
+            {"email": "not an email"},
+            {"email": "TAKEN@example.com"},
+            {"email": null},
+            {"email": "b@example.org", "name": "ignored"}
+        ]"#;
+        let report = import_judges(&state, Format::Json, json, false).unwrap();
+        let emails: Vec<&str> = report.imported.iter().map(|j| j.email.as_str()).collect();
+        assert_eq!(emails, vec!["a@example.com", "b@example.org"]);
+        let rows: Vec<usize> = report.rejected.iter().map(|r| r.row).collect();
+        assert_eq!(rows, vec![2, 3, 4, 5]);
+        assert_eq!(state.get_judges().len(), 3);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_import_rejects_malformed_files() {
+        let state = state();
+        for (format, data) in [
+            (Format::Json, "{\"email\": \"a@example.com\"}"),
+            (Format::Csv, "address\na@example.com\n"),
+        ] {
+            assert!(matches!(
+                import_judges(&state, format, data, false),
+                Err(SchedulerError::Validation(_))
+            ));
+        }
+        assert!(import_judges(&state, Format::Csv, "", false)
+            .unwrap()
+            .imported
+            .is_empty());
+        assert_eq!(
+            Format::from_path(Path::new("judges.JSON")),
+            Some(Format::Json)
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_parse_votes() {
+        let csv = "\
+seq,timestamp_ms,period,judge_id,judge_email,match_id,item_a,item_a_name,item_b,item_b_name,winner,score_a,amends,superseded
+0,10,0,j1,a@example.com,m1,x,X,y,Y,B,0.0,,true
+1,20,0,j1,a@example.com,m1,x,X,y,Y,LeanA,0.75,0,false
+";
+        let votes = parse_votes(Format::Csv, csv).unwrap();
+        assert_eq!(votes.len(), 2);
+        assert_eq!((votes[1].i1.as_str(), votes[1].i2.as_str()), ("x", "y"));
+        assert_eq!(votes[1].winner, MatchWinner::LeanA);
+        assert_eq!((votes[0].amends, votes[1].amends), (None, Some(0)));
+
+        let json = r#"[{"seq": 3, "i1": "x", "i2": "y", "winner": "Tie"}]"#;
+        let votes = parse_votes(Format::Json, json).unwrap();
+        assert_eq!((votes[0].seq, votes[0].winner), (3, MatchWinner::Tie));
+
+        for (format, data) in [
+            (
+                Format::Json,
+                r#"[{"seq": 0, "i1": "x", "i2": "y", "winner": "C"}]"#,
+            ),
+            (Format::Csv, "seq,item_a,winner\n0,x,A\n"),
+        ] {
+            assert!(matches!(
+                parse_votes(format, data),
+                Err(SchedulerError::Validation(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::{
+    bradley_terry::algo::{self as bt, Game},
+    rating::{EngineKind, RatingEngine, RatingMode},
+    scheduler::Item,
+    votelog::{self, Vote},
+};
+
+/// Two-sided 95% normal quantile.
+const Z: f64 = 1.96;
+
+/// Resamples used to estimate uncertainty for engines without a rating deviation.
+const BOOTSTRAP_SAMPLES: usize = 200;
+
+/// Fixed so the same vote log always produces the same intervals.
+const BOOTSTRAP_SEED: u64 = 0x5eed;
+
+/// How the ratings on the leaderboard are computed.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingMethod {
+    /// The ratings maintained by the scheduler's rating engine.
+    #[default]
+    Live,
+    /// A Bradley–Terry fit over every verdict at once, which unlike Elo and
+    /// Glicko-2 does not depend on the order verdicts arrived in.
+    BradleyTerry,
+}
+
+impl FromStr for RankingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "live" => Ok(RankingMethod::Live),
+            "bradley_terry" | "bt" => Ok(RankingMethod::BradleyTerry),
+            other => Err(format!("unknown ranking method `{}`", other)),
+        }
+    }
+}
+
+impl fmt::Display for RankingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RankingMethod::Live => write!(f, "live"),
+            RankingMethod::BradleyTerry => write!(f, "bradley_terry"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub id: String,
+    pub name: String,
+    pub location: String,
+    pub rating: f64,
+    /// Standard error of `rating`, on the same scale.
+    pub std_error: f64,
+    /// Bounds of the 95% confidence interval for `rating`.
+    pub low: f64,
+    pub high: f64,
+    pub comparisons: u32,
+    /// Verdicts that went this item's way, including slight preferences.
+    pub wins: u32,
+    pub losses: u32,
+    pub ties: u32,
+    /// Set when the gap to the next entry down is not significant at 95%, so the
+    /// order of the two should not be read into.
+    pub indistinguishable_from_next: bool,
+}
+
+#[derive(Default)]
+struct Record {
+    wins: u32,
+    losses: u32,
+    ties: u32,
+}
+
+fn records(votes: &[&Vote]) -> HashMap<String, Record> {
+    let mut records: HashMap<String, Record> = HashMap::new();
+    for vote in votes {
+        let s1 = vote.winner.score_a();
+        if s1 == 0.5 {
+            records.entry(vote.i1.clone()).or_default().ties += 1;
+            records.entry(vote.i2.clone()).or_default().ties += 1;
+            continue;
+        }
+        let (winner, loser) = if s1 > 0.5 {
+            (&vote.i1, &vote.i2)
+        } else {
+            (&vote.i2, &vote.i1)
+        };
+        records.entry(winner.clone()).or_default().wins += 1;
+        records.entry(loser.clone()).or_default().losses += 1;
+    }
+    records
+}
+
+/// Percentile interval and standard deviation of each item's rating over
+/// replays of the vote log resampled with replacement.
+fn bootstrap(
+    item_ids: &[String],
+    votes: &[&Vote],
+    engine: &dyn RatingEngine,
+    mode: RatingMode,
+    closed_periods: u64,
+) -> HashMap<String, (f64, f64, f64)> {
+    let mut rng = StdRng::seed_from_u64(BOOTSTRAP_SEED);
+    let mut samples: HashMap<String, Vec<f64>> = HashMap::new();
+    for _ in 0..BOOTSTRAP_SAMPLES {
+        let resampled: Vec<Vote> = (0..votes.len())
+            .map(|seq| {
+                let mut vote = votes[rng.gen_range(0..votes.len())].clone();
+                vote.seq = seq as u64;
+                vote.amends = None;
+                vote
+            })
+            .collect();
+        let ratings = votelog::replay(
+            item_ids.iter().cloned(),
+            &resampled,
+            engine,
+            mode,
+            closed_periods,
+        );
+        for (id, rating) in ratings {
+            samples
+                .entry(id)
+                .or_default()
+                .push(engine.display(&rating).rating);
+        }
+    }
+
+    samples
+        .into_iter()
+        .map(|(id, mut xs)| {
+            xs.sort_by(f64::total_cmp);
+            let n = xs.len() as f64;
+            let mean = xs.iter().sum::<f64>() / n;
+            let sd = (xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+            let at = |q: f64| xs[((n - 1.0) * q).round() as usize];
+            (id, (sd, at(0.025), at(0.975)))
+        })
+        .collect()
+}
+
+/// Bradley–Terry rating, standard error and 95% interval of every item.
+fn bradley_terry(items: &[Item], votes: &[&Vote]) -> HashMap<String, (f64, f64, f64, f64)> {
+    let index: HashMap<&str, usize> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| (item.id.as_str(), i))
+        .collect();
+    let games: Vec<Game> = votes
+        .iter()
+        .filter_map(|v| {
+            Some(Game {
+                a: *index.get(v.i1.as_str())?,
+                b: *index.get(v.i2.as_str())?,
+                score: v.winner.score_a(),
+            })
+        })
+        .collect();
+
+    let fit = bt::fit(items.len(), &games);
+    if !fit.converged {
+        tracing::warn!(
+            "Bradley-Terry fit did not converge after {} iterations",
+            fit.iterations
+        );
+    }
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let rating = fit.rating(i);
+            let se = fit.rating_std_error(i);
+            (
+                item.id.clone(),
+                (rating, se, rating - Z * se, rating + Z * se),
+            )
+        })
+        .collect()
+}
+
+/// Ranks the active `items` using `method`. Withdrawn items are left off the
+/// board but still count as opponents for the items they met.
+///
+/// For live rankings Glicko-2 intervals come straight from each item's rating
+/// deviation. Elo has no notion of uncertainty, so its intervals are
+/// bootstrapped from the vote log. Bradley–Terry uses every effective verdict,
+/// including those still pending in an open rating period.
+pub fn build(
+    items: &[Item],
+    votes: &[Vote],
+    engine: &dyn RatingEngine,
+    mode: RatingMode,
+    closed_periods: u64,
+    method: RankingMethod,
+) -> Vec<LeaderboardEntry> {
+    let effective = votelog::effective(votes);
+    let records = records(&effective);
+    let ids: Vec<String> = items.iter().map(|i| i.id.clone()).collect();
+    let estimates = match (method, engine.kind()) {
+        (RankingMethod::BradleyTerry, _) => bradley_terry(items, &effective),
+        (RankingMethod::Live, EngineKind::Elo) if !effective.is_empty() => {
+            let resampled = bootstrap(&ids, &effective, engine, mode, closed_periods);
+            items
+                .iter()
+                .filter_map(|item| {
+                    let &(sd, low, high) = resampled.get(&item.id)?;
+                    Some((item.id.clone(), (item.score, sd, low, high)))
+                })
+                .collect()
+        }
+        (RankingMethod::Live, _) => items
+            .iter()
+            .map(|item| {
+                let (rating, rd) = (item.score, item.rd);
+                (
+                    item.id.clone(),
+                    (rating, rd, rating - Z * rd, rating + Z * rd),
+                )
+            })
+            .collect(),
+    };
+
+    let mut entries: Vec<LeaderboardEntry> = items
+        .iter()
+        .filter(|item| item.active)
+        .map(|item| {
+            let record = records.get(&item.id);
+            let wins = record.map(|r| r.wins).unwrap_or(0);
+            let losses = record.map(|r| r.losses).unwrap_or(0);
+            let ties = record.map(|r| r.ties).unwrap_or(0);
+            let (rating, std_error, low, high) = estimates
+                .get(&item.id)
+                .copied()
+                .unwrap_or((item.score, item.rd, item.score, item.score));
+            LeaderboardEntry {
+                rank: 0,
+                id: item.id.clone(),
+                name: item.name.clone(),
+                location: item.location.clone(),
+                rating,
+                std_error,
+                low,
+                high,
+                comparisons: wins + losses + ties,
+                wins,
+                losses,
+                ties,
+                indistinguishable_from_next: false,
+            }
+        })
+        .collect();
+
+    entries.sort_by(|a, b| {
+        b.rating
+            .total_cmp(&a.rating)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    for i in 0..entries.len() {
+        entries[i].rank = i + 1;
+        if let Some(next) = entries.get(i + 1) {
+            let gap = entries[i].rating - next.rating;
+            let se = (entries[i].std_error.powi(2) + next.std_error.powi(2)).sqrt();
+            entries[i].indistinguishable_from_next = gap <= Z * se;
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::{glicko2::algo::Glicko2, rating::engine_for, scheduler::MatchWinner};
+
+    fn item(id: &str, rating: f64, rd: f64, engine: &dyn RatingEngine) -> Item {
+        let mut item = Item::new(id.to_string(), String::new(), String::new(), engine);
+        item.id = id.to_string();
+        item.set_rating(Glicko2::from_glicko1_vars(rating, 0.06, rd), engine);
+        item
+    }
+
+    fn vote(seq: u64, i1: &str, i2: &str, winner: MatchWinner) -> Vote {
+        let g = Glicko2::new();
+        Vote {
+            seq,
+            judge_id: "judge".to_string(),
+            match_id: format!("m{}", seq),
+            i1: i1.to_string(),
+            i2: i2.to_string(),
+            winner,
+            timestamp_ms: 0,
+            period: 0,
+            pre_i1: g,
+            pre_i2: g,
+            post_i1: g,
+            post_i2: g,
+            amends: None,
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_ranks_and_flags() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let items = vec![
+            item("low", 1300.0, 40.0, engine.as_ref()),
+            item("top", 1800.0, 40.0, engine.as_ref()),
+            item("close", 1780.0, 40.0, engine.as_ref()),
+        ];
+        let votes = vec![
+            vote(0, "top", "low", MatchWinner::A),
+            vote(1, "close", "top", MatchWinner::B),
+            vote(2, "close", "low", MatchWinner::Tie),
+        ];
+
+        let board = build(
+            &items,
+            &votes,
+            engine.as_ref(),
+            RatingMode::Immediate,
+            0,
+            RankingMethod::Live,
+        );
+        let ids: Vec<&str> = board.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["top", "close", "low"]);
+        assert_eq!(board[0].rank, 1);
+        assert_eq!((board[0].wins, board[0].losses), (2, 0));
+        assert_eq!(board[1].comparisons, 2);
+        assert_eq!((board[1].losses, board[1].ties), (1, 1));
+        assert!(board[0].low < board[0].rating && board[0].rating < board[0].high);
+        assert!(board[0].indistinguishable_from_next);
+        assert!(!board[1].indistinguishable_from_next);
+        assert!(!board[2].indistinguishable_from_next);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_elo_intervals_are_bootstrapped() {
+        let engine = engine_for(EngineKind::Elo);
+        let mut items: Vec<Item> = ["a", "b", "c"]
+            .iter()
+            .map(|id| item(id, 1000.0, 0.0, engine.as_ref()))
+            .collect();
+        let votes: Vec<Vote> = (0..20)
+            .map(|seq| match seq % 3 {
+                0 => vote(seq, "a", "b", MatchWinner::A),
+                1 => vote(seq, "b", "c", MatchWinner::A),
+                _ => vote(seq, "c", "a", MatchWinner::B),
+            })
+            .collect();
+        let ratings = votelog::replay(
+            ["a", "b", "c"].map(String::from),
+            &votes,
+            engine.as_ref(),
+            RatingMode::Immediate,
+            0,
+        );
+        for item in items.iter_mut() {
+            item.set_rating(ratings[&item.id], engine.as_ref());
+        }
+
+        let board = build(
+            &items,
+            &votes,
+            engine.as_ref(),
+            RatingMode::Immediate,
+            0,
+            RankingMethod::Live,
+        );
+        assert!(board.iter().all(|e| e.std_error > 0.0 && e.low < e.high));
+        assert_eq!(
+            board,
+            build(
+                &items,
+                &votes,
+                engine.as_ref(),
+                RatingMode::Immediate,
+                0,
+                RankingMethod::Live
+            )
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_bradley_terry_ignores_vote_order() {
+        let engine = engine_for(EngineKind::Elo);
+        let items: Vec<Item> = ["a", "b", "c"]
+            .iter()
+            .map(|id| item(id, 1000.0, 0.0, engine.as_ref()))
+            .collect();
+        let votes = vec![
+            vote(0, "a", "b", MatchWinner::A),
+            vote(1, "a", "c", MatchWinner::A),
+            vote(2, "b", "c", MatchWinner::A),
+            vote(3, "c", "b", MatchWinner::A),
+            vote(4, "a", "b", MatchWinner::B),
+        ];
+        let reordered: Vec<Vote> = votes
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(seq, v)| Vote {
+                seq: seq as u64,
+                ..v.clone()
+            })
+            .collect();
+
+        let method = RankingMethod::BradleyTerry;
+        let mode = RatingMode::Immediate;
+        let board = build(&items, &votes, engine.as_ref(), mode, 0, method);
+        let again = build(&items, &reordered, engine.as_ref(), mode, 0, method);
+        assert_eq!(board[0].id, "a");
+        for (x, y) in board.iter().zip(&again) {
+            assert_eq!(x.id, y.id);
+            assert!((x.rating - y.rating).abs() < 1e-6);
+        }
+        assert!(board.iter().all(|e| e.std_error > 0.0));
+    }
+}
@@ -1,59 +1,773 @@
+mod auth;
+mod bradley_terry;
+mod config;
 mod elo;
+mod error;
+mod event;
+mod export;
+mod glicko2;
+mod import;
+mod leaderboard;
+mod pairing;
+mod rating;
 mod scheduler;
+mod simulate;
+mod storage;
+mod votelog;
 
+use auth::{Auth, AuthJudge};
 use axum::{
-    extract::State,
-    http::StatusCode,
-    routing::{get, post},
+    extract::{FromRef, Path, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing::{get, patch, post},
     Json, Router,
 };
-use scheduler::{Item, Judge, MatchPair, MatchWinner};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use config::Config;
+use error::{JsonBody, QueryParams, SchedulerError};
+use event::{Event, EventConfig, EventId, EventState, EventSummary, Events};
+use export::{JudgeStats, VerdictRow};
+use import::{Format, ImportReport};
+use leaderboard::{LeaderboardEntry, RankingMethod};
+use rating::{EngineKind, RatingMode};
+use scheduler::{
+    Item, ItemUnavailable, ItemUpdate, Judge, JudgeUpdate, Lease, MatchPair, MatchWinner, Skip,
+    SkipReason, States,
+};
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashMap, env, fmt::Display, fs, path::PathBuf, process, sync::Arc, time::Duration,
+};
+use votelog::Vote;
+
+#[derive(Clone)]
+struct AppState {
+    events: Events,
+    auth: Arc<Auth>,
+}
+
+impl FromRef<AppState> for Events {
+    fn from_ref(state: &AppState) -> Events {
+        state.events.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Auth> {
+    fn from_ref(state: &AppState) -> Arc<Auth> {
+        state.auth.clone()
+    }
+}
+
+#[derive(Parser)]
+#[command(version, about = "Ranks items through pairwise comparisons by judges")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+/// Settings given on the command line, which take precedence over the
+/// environment and the config file.
+#[derive(Args)]
+struct ConfigArgs {
+    /// TOML file to read settings from.
+    #[arg(long, global = true, env = "RANKER_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address to listen on, e.g. `0.0.0.0:3000`.
+    #[arg(long, global = true)]
+    bind: Option<String>,
+    /// Directory holding one SQLite file per event.
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Rating engine for new events, `glicko2` or `elo`.
+    #[arg(long, global = true)]
+    engine: Option<String>,
+    /// Rounds of seed matches for new events.
+    #[arg(long, global = true)]
+    seed_rounds: Option<usize>,
+    /// How long a judge holds a match for new events, in seconds.
+    #[arg(long, global = true)]
+    lease_secs: Option<u64>,
+    /// Sets any other setting by its name in the config file, e.g.
+    /// `--set event.elo.k=24`. Quote the value to force a string.
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+    set: Vec<(String, String)>,
+}
+
+fn parse_setting(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got `{}`", s)),
+    }
+}
+
+impl ConfigArgs {
+    fn load(&self) -> Result<Config, String> {
+        let text = |value: &str| toml::Value::String(value.to_string());
+        let mut overrides: Vec<(String, toml::Value)> = self
+            .set
+            .iter()
+            .map(|(key, value)| (key.clone(), config::parse_value(value)))
+            .collect();
+        let flags = [
+            ("bind", self.bind.as_deref().map(text)),
+            (
+                "data_dir",
+                self.data_dir
+                    .as_ref()
+                    .map(|dir| text(&dir.to_string_lossy())),
+            ),
+            ("event.engine", self.engine.as_deref().map(text)),
+            (
+                "event.seed_rounds",
+                self.seed_rounds.map(|n| toml::Value::Integer(n as i64)),
+            ),
+            (
+                "event.lease_secs",
+                self.lease_secs
+                    .map(|secs| toml::Value::Integer(secs as i64)),
+            ),
+        ];
+        overrides.extend(
+            flags
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), value?))),
+        );
+        Config::load(self.config.as_deref(), &env::vars().collect(), &overrides)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the API server. This is the default.
+    Serve,
+    /// Add items or judges to an event from a CSV or JSON file. Run it while
+    /// the server is stopped, as a running server will not see the changes.
+    Import(ImportArgs),
+    /// Write an event's ranking, verdicts, judge statistics or HTML report to
+    /// standard output or a file.
+    Export(ExportArgs),
+    /// Recompute an event's ratings by replaying its vote log, or one saved
+    /// to a file, optionally with another rating engine. The event is not
+    /// changed; the resulting ranking is printed.
+    Replay(ReplayArgs),
+    /// Run a synthetic event in memory with judges of known taste, and report
+    /// how well the final ranking recovers the true order. New-event settings
+    /// such as `--engine` and `--seed-rounds` apply.
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    /// What the file holds.
+    kind: ImportKind,
+    file: PathBuf,
+    #[arg(long, default_value = event::DEFAULT_EVENT)]
+    event: String,
+    /// `csv` or `json`. Defaults to the file extension.
+    #[arg(long)]
+    format: Option<Format>,
+    /// Report what would be imported without changing anything.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ImportKind {
+    Items,
+    Judges,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// What to export.
+    kind: ExportKind,
+    #[arg(long, default_value = event::DEFAULT_EVENT)]
+    event: String,
+    #[command(flatten)]
+    ranking: RankingArgs,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ExportKind {
+    Leaderboard,
+    Votes,
+    Judges,
+    Report,
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    #[arg(long, default_value = event::DEFAULT_EVENT)]
+    event: String,
+    /// A vote log saved from `GET /votes` or `GET /export/votes`, as CSV or
+    /// JSON, to replay instead of the event's own.
+    #[arg(long)]
+    votes: Option<PathBuf>,
+    /// Rating engine to replay with, `glicko2` or `elo`. Defaults to the
+    /// event's; parameters are taken from the event's configuration.
+    #[arg(long)]
+    using: Option<EngineKind>,
+    #[command(flatten)]
+    ranking: RankingArgs,
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    #[arg(long, default_value_t = 20)]
+    items: usize,
+    #[arg(long, default_value_t = 10)]
+    judges: usize,
+    /// Verdicts to cast, fewer if the judges run out of matches.
+    #[arg(long, default_value_t = 300)]
+    verdicts: usize,
+    /// How erratic judges are. An item this much stronger than another, in
+    /// standard deviations of item strength, is preferred 73% of the time.
+    #[arg(long, default_value_t = 0.5)]
+    noise: f64,
+    /// Fixes item strengths, pairing and verdicts, so a run can be repeated
+    /// exactly. A random seed is logged otherwise.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// `live` or `bradley_terry`.
+    #[arg(long, default_value_t = RankingMethod::Live)]
+    method: RankingMethod,
+    /// File to write the JSON report to instead of standard output.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+/// How results are ranked and where they are written.
+#[derive(Args)]
+struct RankingArgs {
+    /// `live` or `bradley_terry`.
+    #[arg(long, default_value_t = RankingMethod::Live)]
+    method: RankingMethod,
+    /// Rank as if withdrawn items had never taken part.
+    #[arg(long)]
+    exclude_inactive: bool,
+    /// `csv` or `json`. Defaults to the extension of `--output`, then JSON.
+    #[arg(long)]
+    format: Option<Format>,
+    /// File to write to instead of standard output.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+impl RankingArgs {
+    fn format(&self) -> Format {
+        self.format
+            .or_else(|| self.output.as_deref().and_then(Format::from_path))
+            .unwrap_or_default()
+    }
+
+    /// Writes `rows` in the chosen format.
+    fn write<T: Serialize>(&self, rows: &[T]) {
+        let text = match self.format() {
+            Format::Json => serde_json::to_string_pretty(rows)
+                .map(|json| json + "\n")
+                .unwrap_or_else(|err| exit_with(err)),
+            Format::Csv => export::to_csv(rows).unwrap_or_else(|err| exit_with(err)),
+        };
+        write_output(self.output.as_ref(), &text);
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    // logs go to stderr so subcommands can print their results on stdout
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let config = cli.config.load().unwrap_or_else(|err| exit_with(err));
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Import(args) => import(&config, args),
+        Command::Export(args) => export(&config, args),
+        Command::Replay(args) => replay(&config, args),
+        Command::Simulate(args) => simulate(&config, args),
+    }
+}
+
+fn exit_with(err: impl Display) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(1)
+}
+
+/// Loads every event from the data directory. New events are configured
+/// from `config.event`.
+fn load_events(config: &Config) -> Events {
+    Events::open(config.data_dir.clone(), config.event.clone()).unwrap_or_else(|err| {
+        exit_with(format!(
+            "failed to load events from {}: {}",
+            config.data_dir.display(),
+            err
+        ))
+    })
+}
+
+async fn serve(config: Config) {
+    let events = load_events(&config);
+    for event in events.list() {
+        tracing::info!(
+            "event {} uses {} rating engine in {:?} mode with {} pairing",
+            event.id,
+            event.config.engine,
+            event.config.rating_mode,
+            event.config.pairing
+        );
+    }
+
+    let admin_keys = if config.admin_keys.is_empty() {
+        let key = auth::new_token();
+        tracing::warn!(
+            "no admin key is configured, using generated admin key {}",
+            key
+        );
+        vec![key]
+    } else {
+        config.admin_keys.clone()
+    };
+    let signing_key = match &config.secret {
+        Some(secret) => secret.clone().into_bytes(),
+        None => {
+            tracing::warn!("no secret is configured, magic links will stop working on restart");
+            auth::new_token().into_bytes()
+        }
+    };
+    let auth = Auth::new(&admin_keys, signing_key, config.public_url.clone());
+
+    let housekeeping = events.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            housekeeping.tick();
+        }
+    });
+
+    let app = app(AppState {
+        events,
+        auth: Arc::new(auth),
+    });
+
+    // run our app with hyper
+    // `axum::Server` is a re-export of `hyper::Server`
+    let server = axum::Server::try_bind(&config.bind)
+        .unwrap_or_else(|err| exit_with(format!("cannot listen on {}: {}", config.bind, err)));
+    tracing::info!("listening on {}", config.bind);
+    server.serve(app.into_make_service()).await.unwrap();
+}
+
+fn import(config: &Config, args: ImportArgs) {
+    let events = load_events(config);
+    let event = events.get(&args.event).unwrap_or_else(|err| exit_with(err));
+    let format = args
+        .format
+        .or_else(|| Format::from_path(&args.file))
+        .unwrap_or_else(|| exit_with("cannot tell the file format from its name, pass --format"));
+    let data = fs::read_to_string(&args.file).unwrap_or_else(|err| exit_with(err));
 
-    let state = scheduler::SchedulerState::new();
+    let report = match args.kind {
+        ImportKind::Items => import::import_items(&event.state, format, &data, args.dry_run)
+            .map(|report| serde_json::to_string_pretty(&report)),
+        ImportKind::Judges => {
+            // magic links can only be signed with the server's secret
+            let auth = match &config.secret {
+                Some(secret) => {
+                    Auth::new(&[], secret.clone().into_bytes(), config.public_url.clone())
+                }
+                None => Auth::new(&[], vec![], None),
+            };
+            import::import_judges(&event.state, format, &data, args.dry_run).map(|report| {
+                serde_json::to_string_pretty(&judge_credentials(
+                    &auth,
+                    &event.id,
+                    &event.state,
+                    report,
+                ))
+            })
+        }
+    };
+    match report {
+        Ok(Ok(json)) => println!("{}", json),
+        Ok(Err(err)) => exit_with(err),
+        Err(err) => exit_with(err),
+    }
+}
 
-    let app = Router::new()
+/// Writes `text` to `output`, or to standard output when there is none.
+fn write_output(output: Option<&PathBuf>, text: &str) {
+    match output {
+        Some(path) => fs::write(path, text)
+            .unwrap_or_else(|err| exit_with(format!("cannot write {}: {}", path.display(), err))),
+        None => print!("{}", text),
+    }
+}
+
+fn export(config: &Config, args: ExportArgs) {
+    let events = load_events(config);
+    let event = events.get(&args.event).unwrap_or_else(|err| exit_with(err));
+    let state = &event.state;
+    let ranking = &args.ranking;
+    let leaderboard = || state.leaderboard(ranking.method, ranking.exclude_inactive);
+    match args.kind {
+        ExportKind::Leaderboard => ranking.write(&leaderboard()),
+        ExportKind::Votes => ranking.write(&export::verdicts(state)),
+        ExportKind::Judges => ranking.write(&export::judge_stats(state, &leaderboard())),
+        ExportKind::Report => write_output(
+            ranking.output.as_ref(),
+            &export::html_report(
+                &event.config.name,
+                ranking.method,
+                &leaderboard(),
+                votelog::effective(&state.get_votes()).len(),
+                state.get_judges().len(),
+                votelog::now_ms(),
+            ),
+        ),
+    }
+}
+
+fn replay(config: &Config, args: ReplayArgs) {
+    let events = load_events(config);
+    let event = events.get(&args.event).unwrap_or_else(|err| exit_with(err));
+    let votes = match &args.votes {
+        Some(path) => {
+            let data = fs::read_to_string(path).unwrap_or_else(|err| exit_with(err));
+            let format = Format::from_path(path).unwrap_or_default();
+            import::parse_votes(format, &data).unwrap_or_else(|err| exit_with(err))
+        }
+        None => event.state.get_votes(),
+    };
+    let engine = EventConfig {
+        engine: args.using.unwrap_or(event.config.engine),
+        ..event.config.clone()
+    }
+    .engine();
+    tracing::info!(
+        "replaying {} votes with the {} rating engine",
+        votes.len(),
+        engine.kind()
+    );
+    let replayed = event.state.replayed_with(engine, votes);
+    args.ranking
+        .write(&replayed.leaderboard(args.ranking.method, args.ranking.exclude_inactive));
+}
+
+fn simulate(config: &Config, args: SimulateArgs) {
+    let seed = args.seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        tracing::info!("simulating with seed {}", seed);
+        seed
+    });
+    let simulation = simulate::Simulation {
+        items: args.items,
+        judges: args.judges,
+        verdicts: args.verdicts,
+        noise: args.noise,
+        seed,
+    };
+    let report =
+        simulate::run(&config.event, &simulation, args.method).unwrap_or_else(|err| exit_with(err));
+    let json = serde_json::to_string_pretty(&report).unwrap_or_else(|err| exit_with(err));
+    write_output(args.output.as_ref(), &(json + "\n"));
+}
+
+fn app(state: AppState) -> Router {
+    let admin_events = Router::new()
+        .route("/events", post(create_event))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_admin,
+        ));
+    // every event is served under /events/{id}, and the default event at the root as well
+    Router::new()
+        .route("/events", get(get_events))
+        .merge(admin_events)
+        .nest(
+            "/events/:event_id",
+            event_routes(&state).route("/", get(get_event)),
+        )
+        .merge(event_routes(&state))
+        .fallback(error::not_found)
+        .with_state(state)
+}
+
+/// Results are public, judges act through their own token and everything
+/// that changes the event is for admins only.
+fn event_routes(state: &AppState) -> Router<AppState> {
+    let public = Router::new()
+        .route("/item", get(get_items))
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/export/leaderboard", get(export_leaderboard))
+        .route("/export/report.html", get(export_report))
+        .route("/rating_period", get(get_rating_period))
+        .route("/judge/login", post(login_judge));
+    let judge = Router::new()
+        .route("/matches/for_judge", post(request_match_for_judge))
+        .route("/matches/judge", post(judge_match));
+    let admin = Router::new()
         .route("/judge", post(create_judge).get(get_judges))
-        .route("/item", post(create_item).get(get_items))
+        .route(
+            "/judge/:judge_id",
+            patch(update_judge).delete(deactivate_judge),
+        )
+        .route("/item", post(create_item))
+        .route("/item/:item_id", patch(update_item).delete(withdraw_item))
+        .route("/import/items", post(bulk_import_items))
+        .route("/import/judges", post(bulk_import_judges))
         .route("/scheduler_start", post(start_matchmaking))
         .route("/matches", get(get_matches))
-        .route("/matches/for_judge", post(request_match_for_judge))
-        .route("/matches/judge", post(judge_match))
-        .with_state(state);
+        .route("/leases", get(get_leases))
+        .route("/skips", get(get_skips))
+        .route("/votes", get(get_votes))
+        .route("/export/votes", get(export_votes))
+        .route("/export/judges", get(export_judges))
+        .route("/votes/replay", post(replay_votes))
+        .route("/rating_period/close", post(close_rating_period))
+        .route("/admin/state", get(get_admin_state))
+        .route("/admin/pause", post(pause_judging))
+        .route("/admin/resume", post(resume_judging))
+        .route("/admin/advance", post(advance_to_continuous))
+        .route("/admin/end", post(end_event))
+        .route("/admin/reset", post(reset_event))
+        .route(
+            "/admin/judges/:judge_id/token",
+            post(reissue_judge_token).delete(revoke_judge_token),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_admin,
+        ));
+    public.merge(judge).merge(admin)
+}
 
-    // run our app with hyper
-    // `axum::Server` is a re-export of `hyper::Server`
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
-    tracing::debug!("listening on {}", addr);
+async fn get_events(State(events): State<Events>) -> (StatusCode, Json<Vec<EventSummary>>) {
+    (
+        StatusCode::OK,
+        Json(events.list().iter().map(Event::summary).collect()),
+    )
+}
+
+async fn get_event(
+    State(events): State<Events>,
+    Path(event_id): Path<String>,
+) -> Result<(StatusCode, Json<EventSummary>), SchedulerError> {
+    Ok((StatusCode::OK, Json(events.get(&event_id)?.summary())))
+}
+
+#[derive(Deserialize)]
+struct CreateEvent {
+    id: String,
+    /// Any subset of the event configuration; the rest comes from the server defaults.
+    #[serde(default)]
+    config: serde_json::Map<String, serde_json::Value>,
+}
+
+async fn create_event(
+    State(events): State<Events>,
+    JsonBody(payload): JsonBody<CreateEvent>,
+) -> Result<(StatusCode, Json<EventSummary>), SchedulerError> {
+    let event = events.create(&payload.id, payload.config)?;
+    Ok((StatusCode::CREATED, Json(event.summary())))
 }
 
-async fn get_judges(
-    State(state): State<scheduler::SchedulerState>,
-) -> (StatusCode, Json<Vec<Judge>>) {
+async fn get_judges(EventState(state): EventState) -> (StatusCode, Json<Vec<Judge>>) {
     (StatusCode::OK, Json(state.get_judges()))
 }
 
+/// A judge along with the credentials they sign in with. The token is only
+/// ever shown here, and left out when nothing was created.
+#[derive(Serialize)]
+struct JudgeCredentials {
+    #[serde(flatten)]
+    judge: Judge,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    /// Only issued when `RANKER_PUBLIC_URL` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    magic_link: Option<String>,
+}
+
+fn credentials(
+    auth: &Auth,
+    event_id: &str,
+    state: &scheduler::SchedulerState,
+    judge: Judge,
+) -> JudgeCredentials {
+    JudgeCredentials {
+        token: Some(state.issue_token(&judge.id)),
+        magic_link: auth.magic_link(event_id, &judge, votelog::now_ms()),
+        judge,
+    }
+}
+
 async fn create_judge(
-    // this argument tells axum to parse the request body
-    // as JSON into a `CreateUser` type
-    State(state): State<scheduler::SchedulerState>,
-    Json(payload): Json<CreateJudge>,
-) -> (StatusCode, Json<Judge>) {
-    // insert your application logic here
-    let user = Judge::new(payload.email);
-    state.add_judge(user.clone());
-    // this will be converted into a JSON response
-    // with a status code of `201 Created`
-    (StatusCode::CREATED, Json(user))
+    State(auth): State<Arc<Auth>>,
+    EventId(event_id): EventId,
+    EventState(state): EventState,
+    JsonBody(payload): JsonBody<CreateJudge>,
+) -> Result<(StatusCode, Json<JudgeCredentials>), SchedulerError> {
+    let judge = state.create_judge(&payload.email)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(credentials(&auth, &event_id, &state, judge)),
+    ))
+}
+
+#[derive(Deserialize)]
+struct MagicLink {
+    judge: String,
+    expires: u64,
+    sig: String,
+}
+
+/// Exchanges a magic link for a new judge token, which replaces the judge's
+/// previous one. This is a POST so that link previews and scanners fetching
+/// the link cannot sign the judge out.
+async fn login_judge(
+    State(auth): State<Arc<Auth>>,
+    EventId(event_id): EventId,
+    EventState(state): EventState,
+    QueryParams(link): QueryParams<MagicLink>,
+) -> Result<(StatusCode, Json<JudgeCredentials>), SchedulerError> {
+    let judge = state
+        .get_judge(&link.judge)
+        .filter(|judge| {
+            auth.verify_magic_link(&event_id, judge, link.expires, &link.sig, votelog::now_ms())
+        })
+        .ok_or_else(|| SchedulerError::Unauthorized("invalid or expired magic link".to_string()))?;
+    if !judge.active {
+        return Err(SchedulerError::Forbidden(
+            "judge has been deactivated".to_string(),
+        ));
+    }
+    Ok((
+        StatusCode::OK,
+        Json(JudgeCredentials {
+            token: Some(state.issue_token(&judge.id)),
+            magic_link: None,
+            judge,
+        }),
+    ))
+}
+
+/// Issues a new token and magic link for a judge who lost theirs. The old
+/// token stops working.
+async fn reissue_judge_token(
+    State(auth): State<Arc<Auth>>,
+    EventId(event_id): EventId,
+    EventState(state): EventState,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<JudgeCredentials>), SchedulerError> {
+    let judge_id = &params["judge_id"];
+    let judge = state
+        .get_judge(judge_id)
+        .ok_or_else(|| SchedulerError::NotFound(format!("judge {} does not exist", judge_id)))?;
+    if !judge.active {
+        return Err(SchedulerError::Forbidden(
+            "judge has been deactivated".to_string(),
+        ));
+    }
+    Ok((
+        StatusCode::CREATED,
+        Json(credentials(&auth, &event_id, &state, judge)),
+    ))
+}
+
+/// Signs a judge out everywhere until they are issued a new token.
+async fn revoke_judge_token(
+    EventState(state): EventState,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<Judge>), SchedulerError> {
+    let judge_id = &params["judge_id"];
+    let judge = state
+        .get_judge(judge_id)
+        .ok_or_else(|| SchedulerError::NotFound(format!("judge {} does not exist", judge_id)))?;
+    state.revoke_tokens(&judge.id);
+    Ok((StatusCode::OK, Json(judge)))
+}
+
+/// Credentials for every judge in an import report. Dry runs create no
+/// judges, so they get none.
+fn judge_credentials(
+    auth: &Auth,
+    event_id: &str,
+    state: &scheduler::SchedulerState,
+    report: ImportReport<Judge>,
+) -> ImportReport<JudgeCredentials> {
+    let dry_run = report.dry_run;
+    report.map(|judge| match dry_run {
+        true => JudgeCredentials {
+            judge,
+            token: None,
+            magic_link: None,
+        },
+        false => credentials(auth, event_id, state, judge),
+    })
+}
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    /// Defaults to CSV for a `text/csv` body and JSON otherwise.
+    format: Option<Format>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl ImportQuery {
+    fn format(&self, headers: &HeaderMap) -> Format {
+        let csv = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("csv"));
+        self.format
+            .unwrap_or(if csv { Format::Csv } else { Format::Json })
+    }
+
+    fn status(&self) -> StatusCode {
+        if self.dry_run {
+            StatusCode::OK
+        } else {
+            StatusCode::CREATED
+        }
+    }
+}
+
+async fn bulk_import_items(
+    EventState(state): EventState,
+    QueryParams(query): QueryParams<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<ImportReport<Item>>), SchedulerError> {
+    let report = import::import_items(&state, query.format(&headers), &body, query.dry_run)?;
+    Ok((query.status(), Json(report)))
+}
+
+async fn bulk_import_judges(
+    State(auth): State<Arc<Auth>>,
+    EventId(event_id): EventId,
+    EventState(state): EventState,
+    QueryParams(query): QueryParams<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<ImportReport<JudgeCredentials>>), SchedulerError> {
+    let report = import::import_judges(&state, query.format(&headers), &body, query.dry_run)?;
+    Ok((
+        query.status(),
+        Json(judge_credentials(&auth, &event_id, &state, report)),
+    ))
 }
 
 // the input to our `create_user` handler
@@ -63,25 +777,169 @@ struct CreateJudge {
 }
 
 async fn create_item(
-    // this argument tells axum to parse the request body
-    // as JSON into a `CreateUser` type
-    State(state): State<scheduler::SchedulerState>,
-    Json(payload): Json<CreateItem>,
-) -> (StatusCode, &'static str) {
-    // insert your application logic here
-    let item = Item::new(payload.name, payload.location, payload.description);
-    state.add_item(item);
-    // this will be converted into a JSON response
-    // with a status code of `201 Created`
-    (StatusCode::CREATED, "success")
+    EventState(state): EventState,
+    JsonBody(payload): JsonBody<CreateItem>,
+) -> Result<(StatusCode, &'static str), SchedulerError> {
+    state.create_item(&payload.name, payload.location, payload.description)?;
+    Ok((StatusCode::CREATED, "success"))
 }
 
-async fn get_items(
-    State(state): State<scheduler::SchedulerState>,
-) -> (StatusCode, Json<Vec<Box<Item>>>) {
+async fn get_items(EventState(state): EventState) -> (StatusCode, Json<Vec<Item>>) {
     (StatusCode::OK, Json(state.get_items()))
 }
 
+async fn update_item(
+    EventState(state): EventState,
+    Path(params): Path<HashMap<String, String>>,
+    JsonBody(payload): JsonBody<ItemUpdate>,
+) -> Result<(StatusCode, Json<Item>), SchedulerError> {
+    let item = state.update_item(&params["item_id"], payload)?;
+    Ok((StatusCode::OK, Json(item)))
+}
+
+/// Items are never deleted outright, since verdicts refer to them; this
+/// withdraws the item instead.
+async fn withdraw_item(
+    EventState(state): EventState,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<Item>), SchedulerError> {
+    let update = ItemUpdate {
+        active: Some(false),
+        ..ItemUpdate::default()
+    };
+    let item = state.update_item(&params["item_id"], update)?;
+    Ok((StatusCode::OK, Json(item)))
+}
+
+async fn update_judge(
+    EventState(state): EventState,
+    Path(params): Path<HashMap<String, String>>,
+    JsonBody(payload): JsonBody<JudgeUpdate>,
+) -> Result<(StatusCode, Json<Judge>), SchedulerError> {
+    let judge = state.update_judge(&params["judge_id"], payload)?;
+    Ok((StatusCode::OK, Json(judge)))
+}
+
+/// Like items, judges are deactivated rather than deleted.
+async fn deactivate_judge(
+    EventState(state): EventState,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<Judge>), SchedulerError> {
+    let update = JudgeUpdate {
+        active: Some(false),
+        ..JudgeUpdate::default()
+    };
+    let judge = state.update_judge(&params["judge_id"], update)?;
+    Ok((StatusCode::OK, Json(judge)))
+}
+
+#[derive(Deserialize)]
+struct LeaderboardQuery {
+    #[serde(default)]
+    method: RankingMethod,
+    /// Also drop verdicts involving withdrawn items.
+    #[serde(default)]
+    exclude_inactive: bool,
+}
+
+/// Ranking can mean replaying the vote log many times over, so it runs on the
+/// blocking pool rather than holding up an async worker.
+async fn ranked(
+    state: scheduler::SchedulerState,
+    method: RankingMethod,
+    exclude_inactive: bool,
+) -> Result<Vec<LeaderboardEntry>, SchedulerError> {
+    tokio::task::spawn_blocking(move || state.leaderboard(method, exclude_inactive))
+        .await
+        .map_err(|err| SchedulerError::Internal(format!("failed to rank items: {}", err)))
+}
+
+async fn get_leaderboard(
+    EventState(state): EventState,
+    QueryParams(query): QueryParams<LeaderboardQuery>,
+) -> Result<(StatusCode, Json<Vec<LeaderboardEntry>>), SchedulerError> {
+    let leaderboard = ranked(state, query.method, query.exclude_inactive).await?;
+    Ok((StatusCode::OK, Json(leaderboard)))
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: Format,
+    #[serde(default)]
+    method: RankingMethod,
+    #[serde(default)]
+    exclude_inactive: bool,
+}
+
+fn exported<T: Serialize>(
+    format: Format,
+    name: &str,
+    rows: Vec<T>,
+) -> Result<Response, SchedulerError> {
+    Ok(match format {
+        Format::Json => (StatusCode::OK, Json(rows)).into_response(),
+        Format::Csv => (
+            StatusCode::OK,
+            [
+                (CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.csv\"", name),
+                ),
+            ],
+            export::to_csv(&rows)?,
+        )
+            .into_response(),
+    })
+}
+
+async fn export_leaderboard(
+    EventState(state): EventState,
+    QueryParams(query): QueryParams<ExportQuery>,
+) -> Result<Response, SchedulerError> {
+    let leaderboard = ranked(state, query.method, query.exclude_inactive).await?;
+    exported::<LeaderboardEntry>(query.format, "leaderboard", leaderboard)
+}
+
+async fn export_votes(
+    EventState(state): EventState,
+    QueryParams(query): QueryParams<ExportQuery>,
+) -> Result<Response, SchedulerError> {
+    exported::<VerdictRow>(query.format, "votes", export::verdicts(&state))
+}
+
+/// Agreement is measured against the ranking chosen with `method`.
+async fn export_judges(
+    EventState(state): EventState,
+    QueryParams(query): QueryParams<ExportQuery>,
+) -> Result<Response, SchedulerError> {
+    let leaderboard = ranked(state.clone(), query.method, query.exclude_inactive).await?;
+    exported::<JudgeStats>(
+        query.format,
+        "judges",
+        export::judge_stats(&state, &leaderboard),
+    )
+}
+
+async fn export_report(
+    State(events): State<Events>,
+    EventId(event_id): EventId,
+    QueryParams(query): QueryParams<ExportQuery>,
+) -> Result<Html<String>, SchedulerError> {
+    let event = events.get(&event_id)?;
+    let state = &event.state;
+    let leaderboard = ranked(state.clone(), query.method, query.exclude_inactive).await?;
+    Ok(Html(export::html_report(
+        &event.config.name,
+        query.method,
+        &leaderboard,
+        votelog::effective(&state.get_votes()).len(),
+        state.get_judges().len(),
+        votelog::now_ms(),
+    )))
+}
+
 #[derive(Deserialize)]
 struct CreateItem {
     name: String,
@@ -91,77 +949,337 @@ struct CreateItem {
 
 #[derive(Deserialize)]
 struct SeedStart {
-    n: usize,
+    /// Rounds of seed matches, defaulting to the event's `seed_rounds`.
+    n: Option<usize>,
 }
 
 async fn start_matchmaking(
     // this argument tells axum to parse the request body
     // as JSON into a `CreateUser` type
-    State(state): State<scheduler::SchedulerState>,
-    Json(payload): Json<SeedStart>,
-) -> (StatusCode, &'static str) {
+    EventState(state): EventState,
+    JsonBody(payload): JsonBody<SeedStart>,
+) -> Result<(StatusCode, &'static str), SchedulerError> {
     // insert your application logic here
-    let status = state.seed_start(payload.n);
-    if !status {
-        return (
-            StatusCode::BAD_REQUEST,
-            "failed to start (likely already started)",
-        );
-    }
+    state.seed_start(payload.n.unwrap_or(state.options().seed_rounds))?;
     // this will be converted into a JSON response
     // with a status code of `201 Created`
-    (StatusCode::CREATED, "success")
-}
-
-async fn get_matches(
-    State(state): State<scheduler::SchedulerState>,
-) -> (StatusCode, Json<Vec<Arc<MatchPair>>>) {
-    if let Ok(matches) = state.get_match_pairs() {
-        (StatusCode::OK, Json(matches))
-    } else {
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(vec![]))
-    }
+    Ok((StatusCode::CREATED, "success"))
 }
 
-#[derive(Serialize)]
-enum ValOrError<T> {
-    Value(T),
-    Error(String),
+async fn get_matches(EventState(state): EventState) -> (StatusCode, Json<Vec<Arc<MatchPair>>>) {
+    (StatusCode::OK, Json(state.get_match_pairs()))
 }
 
 async fn request_match_for_judge(
     // this argument tells axum to parse the request body
     // as JSON into a `CreateUser` type
-    State(state): State<scheduler::SchedulerState>,
-    Json(payload): Json<Judge>,
-) -> (StatusCode, Json<ValOrError<Arc<MatchPair>>>) {
+    EventState(state): EventState,
+    AuthJudge(judge): AuthJudge,
+) -> Result<(StatusCode, Json<Arc<MatchPair>>), SchedulerError> {
     // insert your application logic here
-    let matchpair = state.give_judge_next_match(&payload);
-    match matchpair {
-        Ok(mp) => (StatusCode::CREATED, Json(ValOrError::Value(mp))),
-        Err(err) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ValOrError::Error(err.to_string())),
-        ),
-    }
+    let matchpair = state.give_judge_next_match(&judge)?;
+    Ok((StatusCode::CREATED, Json(matchpair)))
 }
 
 #[derive(Deserialize)]
 struct JudgeMatch {
-    judge: Judge,
     match_id: String,
-    winner: MatchWinner,
+    winner: Option<MatchWinner>,
+    #[serde(default)]
+    amend: bool,
+    /// Set instead of `winner` to pass on the match.
+    skip: Option<SkipMatch>,
+}
+
+#[derive(Deserialize)]
+struct SkipMatch {
+    reason: SkipReason,
+    unavailable: Option<ItemUnavailable>,
 }
 
 async fn judge_match(
     // this argument tells axum to parse the request body
     // as JSON into a `CreateUser` type
-    State(state): State<scheduler::SchedulerState>,
-    Json(payload): Json<JudgeMatch>,
-) -> (StatusCode, &'static str) {
+    EventState(state): EventState,
+    AuthJudge(judge): AuthJudge,
+    JsonBody(payload): JsonBody<JudgeMatch>,
+) -> Result<(StatusCode, &'static str), SchedulerError> {
     // insert your application logic here
-    match state.judge_match(&payload.judge, &payload.match_id, payload.winner) {
-        true => (StatusCode::OK, "judged"),
-        false => (StatusCode::INTERNAL_SERVER_ERROR, "yeah does not exist"),
+    match (payload.winner, payload.skip) {
+        (Some(winner), None) => {
+            state.judge_match(&judge, &payload.match_id, winner, payload.amend)?;
+            Ok((StatusCode::OK, "judged"))
+        }
+        (None, Some(skip)) => {
+            state.skip_match(&judge, &payload.match_id, skip.reason, skip.unavailable)?;
+            Ok((StatusCode::OK, "skipped"))
+        }
+        _ => Err(SchedulerError::Validation(
+            "exactly one of `winner` and `skip` must be set".to_string(),
+        )),
+    }
+}
+
+async fn get_leases(EventState(state): EventState) -> (StatusCode, Json<Vec<Lease>>) {
+    (StatusCode::OK, Json(state.get_leases()))
+}
+
+async fn get_skips(EventState(state): EventState) -> (StatusCode, Json<Vec<Skip>>) {
+    (StatusCode::OK, Json(state.get_skips()))
+}
+
+async fn get_votes(EventState(state): EventState) -> (StatusCode, Json<Vec<Vote>>) {
+    (StatusCode::OK, Json(state.get_votes()))
+}
+
+async fn replay_votes(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<Vec<Item>>), SchedulerError> {
+    let items = tokio::task::spawn_blocking(move || state.replay_votes())
+        .await
+        .map_err(|err| SchedulerError::Internal(format!("failed to replay votes: {}", err)))?;
+    Ok((StatusCode::OK, Json(items)))
+}
+
+#[derive(Serialize)]
+struct RatingPeriod {
+    mode: RatingMode,
+    current: u64,
+}
+
+async fn get_rating_period(EventState(state): EventState) -> (StatusCode, Json<RatingPeriod>) {
+    (
+        StatusCode::OK,
+        Json(RatingPeriod {
+            mode: state.options().rating_mode,
+            current: state.get_rating_period(),
+        }),
+    )
+}
+
+async fn close_rating_period(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<u64>), SchedulerError> {
+    let closed = state.close_rating_period()?;
+    Ok((StatusCode::OK, Json(closed)))
+}
+
+#[derive(Serialize)]
+struct AdminState {
+    state: States,
+    paused: bool,
+    rating_period: u64,
+    items: usize,
+    judges: usize,
+    queued_matches: usize,
+    leases: usize,
+    votes: usize,
+}
+
+fn admin_state(state: &scheduler::SchedulerState) -> AdminState {
+    AdminState {
+        state: state.get_state(),
+        paused: state.is_paused(),
+        rating_period: state.get_rating_period(),
+        items: state.get_items().len(),
+        judges: state.get_judges().len(),
+        queued_matches: state.queue_len(),
+        leases: state.get_leases().len(),
+        votes: state.get_votes().len(),
+    }
+}
+
+async fn get_admin_state(EventState(state): EventState) -> (StatusCode, Json<AdminState>) {
+    (StatusCode::OK, Json(admin_state(&state)))
+}
+
+async fn pause_judging(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<AdminState>), SchedulerError> {
+    state.pause()?;
+    Ok((StatusCode::OK, Json(admin_state(&state))))
+}
+
+async fn resume_judging(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<AdminState>), SchedulerError> {
+    state.resume()?;
+    Ok((StatusCode::OK, Json(admin_state(&state))))
+}
+
+async fn advance_to_continuous(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<AdminState>), SchedulerError> {
+    state.advance_to_continuous()?;
+    Ok((StatusCode::OK, Json(admin_state(&state))))
+}
+
+async fn end_event(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<AdminState>), SchedulerError> {
+    state.end()?;
+    Ok((StatusCode::OK, Json(admin_state(&state))))
+}
+
+async fn reset_event(
+    EventState(state): EventState,
+) -> Result<(StatusCode, Json<AdminState>), SchedulerError> {
+    state.reset()?;
+    Ok((StatusCode::OK, Json(admin_state(&state))))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Method, Request},
+    };
+    use ntest::timeout;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::event::DEFAULT_EVENT;
+
+    const ITEM: &str = r#"{"name": "item", "location": "", "description": ""}"#;
+
+    async fn status(app: &Router, method: Method, uri: &str, bearer: Option<&str>) -> StatusCode {
+        send(app, method, uri, bearer, ITEM).await
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        bearer: Option<&str>,
+        body: &str,
+    ) -> StatusCode {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(bearer) = bearer {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", bearer));
+        }
+        app.clone()
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    #[timeout(1000)]
+    async fn test_roles() {
+        let events = Events::in_memory(EventConfig::default());
+        let state = events.get(DEFAULT_EVENT).unwrap().state;
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        let token = state.issue_token(&judge.id);
+        let app = app(AppState {
+            events,
+            auth: Arc::new(Auth::new(&["admin".to_string()], vec![], None)),
+        });
+
+        for uri in ["/item", "/events/default/item"] {
+            assert_eq!(
+                status(&app, Method::POST, uri, None).await,
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                status(&app, Method::POST, uri, Some(&token)).await,
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                status(&app, Method::POST, uri, Some("wrong")).await,
+                StatusCode::UNAUTHORIZED
+            );
+            let item = format!(
+                r#"{{"name": "{}", "location": "", "description": ""}}"#,
+                uri
+            );
+            assert_eq!(
+                send(&app, Method::POST, uri, Some("admin"), &item).await,
+                StatusCode::CREATED
+            );
+            assert_eq!(status(&app, Method::GET, uri, None).await, StatusCode::OK);
+        }
+        let admin = Some("admin");
+        for (body, expected) in [
+            (
+                r#"{"name": " /ITEM ", "location": "", "description": ""}"#,
+                StatusCode::CONFLICT,
+            ),
+            (
+                r#"{"name": " ", "location": "", "description": ""}"#,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+        ] {
+            assert_eq!(
+                send(&app, Method::POST, "/item", admin, body).await,
+                expected
+            );
+        }
+        for (body, expected) in [
+            (r#"{"email": "JUDGE@example.com"}"#, StatusCode::CONFLICT),
+            (
+                r#"{"email": "not an email"}"#,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (r#"{"email": "new@example.com"}"#, StatusCode::CREATED),
+        ] {
+            assert_eq!(
+                send(&app, Method::POST, "/judge", admin, body).await,
+                expected
+            );
+        }
+        assert_eq!(state.get_judges().len(), 2);
+        assert_eq!(
+            status(&app, Method::POST, "/admin/pause", Some(&token)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&app, Method::POST, "/events", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&app, Method::GET, "/leaderboard", None).await,
+            StatusCode::OK
+        );
+        // matchmaking has not started, so the judge gets past authorization only
+        assert_eq!(
+            status(&app, Method::POST, "/matches/for_judge", Some(&token)).await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status(&app, Method::POST, "/matches/for_judge", Some("admin")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(state.get_items().len(), 2);
+
+        // fetching a magic link must not hand out a token
+        assert_eq!(
+            status(&app, Method::GET, "/judge/login", None).await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        let revoke = format!("/admin/judges/{}/token", judge.id);
+        assert_eq!(
+            status(&app, Method::DELETE, &revoke, Some("admin")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&app, Method::POST, "/matches/for_judge", Some(&token)).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // deactivated judges cannot be signed back in
+        let update = JudgeUpdate {
+            active: Some(false),
+            ..JudgeUpdate::default()
+        };
+        state.update_judge(&judge.id, update).unwrap();
+        assert_eq!(
+            status(&app, Method::POST, &revoke, Some("admin")).await,
+            StatusCode::FORBIDDEN
+        );
     }
 }
@@ -0,0 +1,323 @@
+use rand::{rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    f64::consts::PI,
+    fmt,
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{rating::RatingEngine, scheduler::Item};
+
+/// Rating deviation assumed for engines that do not track one (Elo), so that
+/// closeness in rating still decides which pair is most informative.
+/// Given on the usual 400-point scale and scaled along with the ratings.
+const RD_FLOOR: f64 = 30.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairingKind {
+    Random,
+    InformationGain,
+}
+
+impl FromStr for PairingKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(PairingKind::Random),
+            "information_gain" | "active" => Ok(PairingKind::InformationGain),
+            other => Err(format!("unknown pairing strategy `{}`", other)),
+        }
+    }
+}
+
+impl fmt::Display for PairingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PairingKind::Random => write!(f, "random"),
+            PairingKind::InformationGain => write!(f, "information_gain"),
+        }
+    }
+}
+
+/// Picks the next pair of items to compare once the seeded matches run out.
+pub trait PairSelector: Send + Sync {
+    /// `pair_counts` holds how many matches already exist for each pair of item
+    /// ids and `excluded` the pairs that must not be returned, both keyed with
+    /// [`pair_key`]. Any randomness is drawn from `rng`, so a seeded `rng` and
+    /// the same `items` in the same order give the same pair.
+    fn select(
+        &self,
+        items: &[Item],
+        engine: &dyn RatingEngine,
+        pair_counts: &HashMap<(String, String), u32>,
+        excluded: &HashSet<(String, String)>,
+        rng: &mut StdRng,
+    ) -> Option<(String, String)>;
+}
+
+/// `scale` is the rating engine's [`RatingEngine::scale`].
+pub fn selector_for(kind: PairingKind, scale: f64) -> Arc<dyn PairSelector> {
+    match kind {
+        PairingKind::Random => Arc::new(RandomPairs),
+        PairingKind::InformationGain => Arc::new(InformationGain::new(scale)),
+    }
+}
+
+/// Order-independent key for a pair of item ids.
+pub fn pair_key(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+pub struct RandomPairs;
+
+impl PairSelector for RandomPairs {
+    fn select(
+        &self,
+        items: &[Item],
+        _engine: &dyn RatingEngine,
+        _pair_counts: &HashMap<(String, String), u32>,
+        excluded: &HashSet<(String, String)>,
+        rng: &mut StdRng,
+    ) -> Option<(String, String)> {
+        let mut shuffled: Vec<&Item> = items.iter().collect();
+        shuffled.shuffle(rng);
+        for i in 0..shuffled.len() {
+            for j in (i + 1)..shuffled.len() {
+                if !excluded.contains(&pair_key(&shuffled[i].id, &shuffled[j].id)) {
+                    return Some((shuffled[i].id.clone(), shuffled[j].id.clone()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Prefers pairs whose outcome is most uncertain: near-equal ratings and high
+/// rating deviations. Pairs that were already scheduled are discounted so the
+/// same comparison is not handed out over and over while ratings are static.
+pub struct InformationGain {
+    scale: f64,
+}
+
+impl InformationGain {
+    /// `scale` is the ratings' ten-to-one gap, see [`RatingEngine::scale`].
+    pub fn new(scale: f64) -> Self {
+        InformationGain { scale }
+    }
+}
+
+fn g(rd: f64, q: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * q.powi(2) * rd.powi(2) / PI.powi(2)).sqrt()
+}
+
+/// Expected information from comparing two items, given their displayed
+/// ratings and deviations on a scale where a gap of `scale` means odds of ten
+/// to one.
+pub fn information(r1: f64, rd1: f64, r2: f64, rd2: f64, scale: f64) -> f64 {
+    let floor = RD_FLOOR * scale / 400.0;
+    let rd1 = rd1.max(floor);
+    let rd2 = rd2.max(floor);
+    let combined = (rd1.powi(2) + rd2.powi(2)).sqrt();
+    let q = std::f64::consts::LN_10 / scale;
+    let p = 1.0 / (1.0 + 10f64.powf(-g(combined, q) * (r1 - r2) / scale));
+    p * (1.0 - p) * (rd1.powi(2) + rd2.powi(2))
+}
+
+impl PairSelector for InformationGain {
+    fn select(
+        &self,
+        items: &[Item],
+        engine: &dyn RatingEngine,
+        pair_counts: &HashMap<(String, String), u32>,
+        excluded: &HashSet<(String, String)>,
+        rng: &mut StdRng,
+    ) -> Option<(String, String)> {
+        let mut shuffled: Vec<&Item> = items.iter().collect();
+        // shuffle so that ties are broken randomly
+        shuffled.shuffle(rng);
+        let shown: Vec<_> = shuffled.iter().map(|i| engine.display(&i.rating)).collect();
+
+        let mut best: Option<(f64, usize, usize)> = None;
+        for i in 0..shuffled.len() {
+            for j in (i + 1)..shuffled.len() {
+                let key = pair_key(&shuffled[i].id, &shuffled[j].id);
+                if excluded.contains(&key) {
+                    continue;
+                }
+                let seen = pair_counts.get(&key).copied().unwrap_or(0);
+                let score = information(
+                    shown[i].rating,
+                    shown[i].rd,
+                    shown[j].rating,
+                    shown[j].rd,
+                    self.scale,
+                ) / (1.0 + seen as f64);
+                if best.is_none_or(|(b, _, _)| score > b) {
+                    best = Some((score, i, j));
+                }
+            }
+        }
+
+        best.map(|(_, i, j)| (shuffled[i].id.clone(), shuffled[j].id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        glicko2::algo::Glicko2,
+        rating::{engine_for, EngineKind},
+    };
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(1)
+    }
+
+    fn item(id: &str, rating: f64, rd: f64, engine: &dyn RatingEngine) -> Item {
+        let mut item = Item::new(id.to_string(), String::new(), String::new(), engine);
+        item.id = id.to_string();
+        item.set_rating(Glicko2::from_glicko1_vars(rating, 0.06, rd), engine);
+        item
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_information_prefers_close_and_uncertain() {
+        let info = |r1, rd1, r2, rd2| information(r1, rd1, r2, rd2, 400.0);
+        assert!(info(1500.0, 100.0, 1510.0, 100.0) > info(1500.0, 100.0, 1800.0, 100.0));
+        assert!(info(1500.0, 300.0, 1500.0, 300.0) > info(1500.0, 50.0, 1500.0, 50.0));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_information_follows_scale() {
+        // doubling the scale along with every rating and deviation changes nothing
+        // but the units, and information is measured in squared rating points
+        let usual = information(1500.0, 100.0, 1600.0, 10.0, 400.0);
+        let doubled = information(3000.0, 200.0, 3200.0, 20.0, 800.0);
+        assert!((doubled - 4.0 * usual).abs() < 1e-9 * doubled);
+        assert_ne!(information(1500.0, 100.0, 1600.0, 100.0, 800.0), usual);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_selects_closest_uncertain_pair() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let items = vec![
+            item("a", 1200.0, 80.0, engine.as_ref()),
+            item("b", 1500.0, 250.0, engine.as_ref()),
+            item("c", 1520.0, 250.0, engine.as_ref()),
+            item("d", 1900.0, 80.0, engine.as_ref()),
+        ];
+
+        let (x, y) = InformationGain::new(400.0)
+            .select(
+                &items,
+                engine.as_ref(),
+                &HashMap::new(),
+                &HashSet::new(),
+                &mut rng(),
+            )
+            .unwrap();
+        assert_eq!(pair_key(&x, &y), pair_key("b", "c"));
+
+        let excluded = HashSet::from([pair_key("b", "c")]);
+        let (x, y) = InformationGain::new(400.0)
+            .select(
+                &items,
+                engine.as_ref(),
+                &HashMap::new(),
+                &excluded,
+                &mut rng(),
+            )
+            .unwrap();
+        assert_ne!(pair_key(&x, &y), pair_key("b", "c"));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_repeated_pairs_are_discounted() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let items = vec![
+            item("a", 1500.0, 200.0, engine.as_ref()),
+            item("b", 1500.0, 200.0, engine.as_ref()),
+            item("c", 1540.0, 200.0, engine.as_ref()),
+        ];
+
+        let mut counts = HashMap::new();
+        counts.insert(pair_key("a", "b"), 5);
+        let (x, y) = InformationGain::new(400.0)
+            .select(
+                &items,
+                engine.as_ref(),
+                &counts,
+                &HashSet::new(),
+                &mut rng(),
+            )
+            .unwrap();
+        assert_ne!(pair_key(&x, &y), pair_key("a", "b"));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_needs_two_items() {
+        let engine = engine_for(EngineKind::Elo);
+        let items = vec![item("a", 1000.0, 0.0, engine.as_ref())];
+        assert!(InformationGain::new(400.0)
+            .select(
+                &items,
+                engine.as_ref(),
+                &HashMap::new(),
+                &HashSet::new(),
+                &mut rng()
+            )
+            .is_none());
+        assert!(RandomPairs
+            .select(
+                &items,
+                engine.as_ref(),
+                &HashMap::new(),
+                &HashSet::new(),
+                &mut rng()
+            )
+            .is_none());
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_random_respects_exclusions() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let items = vec![
+            item("a", 1500.0, 200.0, engine.as_ref()),
+            item("b", 1500.0, 200.0, engine.as_ref()),
+            item("c", 1500.0, 200.0, engine.as_ref()),
+        ];
+        let excluded = HashSet::from([pair_key("a", "b"), pair_key("a", "c")]);
+        let mut rng = rng();
+        for _ in 0..10 {
+            let (x, y) = RandomPairs
+                .select(
+                    &items,
+                    engine.as_ref(),
+                    &HashMap::new(),
+                    &excluded,
+                    &mut rng,
+                )
+                .unwrap();
+            assert_eq!(pair_key(&x, &y), pair_key("b", "c"));
+        }
+    }
+}
@@ -0,0 +1,554 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
+
+use crate::{
+    elo,
+    glicko2::algo::{self as glicko2, Constants, Glicko1, Glicko2},
+    scheduler::MatchWinner,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineKind {
+    Elo,
+    Glicko2,
+}
+
+/// When verdicts are turned into rating changes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RatingMode {
+    /// Every verdict updates both items as soon as it is submitted.
+    Immediate,
+    /// Verdicts accumulate and are applied together when the rating period closes.
+    Period,
+}
+
+impl FromStr for RatingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "immediate" => Ok(RatingMode::Immediate),
+            "period" => Ok(RatingMode::Period),
+            other => Err(format!("unknown rating mode `{}`", other)),
+        }
+    }
+}
+
+impl FromStr for EngineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "elo" => Ok(EngineKind::Elo),
+            "glicko2" | "glicko-2" => Ok(EngineKind::Glicko2),
+            other => Err(format!("unknown rating engine `{}`", other)),
+        }
+    }
+}
+
+impl fmt::Display for EngineKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineKind::Elo => write!(f, "elo"),
+            EngineKind::Glicko2 => write!(f, "glicko2"),
+        }
+    }
+}
+
+/// How the Elo engine rates items.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EloConfig {
+    /// How far a single verdict can move a rating.
+    pub k: f64,
+    /// Rating gap at which the stronger item is expected to score ten times
+    /// as much as the weaker one.
+    pub scale: f64,
+    pub initial_rating: f64,
+    /// When set, K shrinks from `k` towards this as an item takes part in more
+    /// verdicts, so early verdicts place items quickly and later ones refine
+    /// the order.
+    pub min_k: Option<f64>,
+    /// Verdicts after which K is halfway from `k` down to `min_k`.
+    pub k_half_life: f64,
+}
+
+impl Default for EloConfig {
+    fn default() -> Self {
+        EloConfig {
+            k: elo::algo::K,
+            scale: elo::algo::SCALE,
+            initial_rating: elo::algo::INITIAL_ELO,
+            min_k: None,
+            k_half_life: 10.0,
+        }
+    }
+}
+
+impl EloConfig {
+    /// The K-factor for an item that has taken part in `comparisons` verdicts.
+    pub fn k_after(&self, comparisons: f64) -> f64 {
+        match self.min_k {
+            Some(min_k) => min_k + (self.k - min_k) * 0.5f64.powf(comparisons / self.k_half_life),
+            None => self.k,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.k > 0.0 && self.k.is_finite()) {
+            return Err("elo.k must be a positive number".to_string());
+        }
+        if !(self.scale > 0.0 && self.scale.is_finite()) {
+            return Err("elo.scale must be a positive number".to_string());
+        }
+        if !self.initial_rating.is_finite() {
+            return Err("elo.initial_rating must be a number".to_string());
+        }
+        if let Some(min_k) = self.min_k {
+            if !(0.0..=self.k).contains(&min_k) {
+                return Err("elo.min_k must be between 0 and elo.k".to_string());
+            }
+            if !(self.k_half_life > 0.0 && self.k_half_life.is_finite()) {
+                return Err("elo.k_half_life must be a positive number".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the Glicko-2 engine rates items.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Glicko2Config {
+    /// Limits how fast volatility can change. Glickman suggests 0.3 to 1.2;
+    /// higher values let ratings react faster to surprising verdicts, which
+    /// suits a short event where few verdicts reach each item.
+    pub tau: f64,
+    /// Tolerance the new volatility is solved to.
+    pub epsilon: f64,
+    pub initial_rating: f64,
+    pub initial_rd: f64,
+    pub initial_volatility: f64,
+}
+
+impl Default for Glicko2Config {
+    fn default() -> Self {
+        Glicko2Config {
+            tau: glicko2::TAU,
+            epsilon: glicko2::EPSILON,
+            initial_rating: glicko2::INITIAL_RATING,
+            initial_rd: glicko2::INITIAL_RD,
+            initial_volatility: glicko2::INITIAL_VOLATILITY,
+        }
+    }
+}
+
+impl Glicko2Config {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.tau > 0.0 && self.tau <= 10.0) {
+            return Err("glicko2.tau must be above 0 and at most 10".to_string());
+        }
+        if !(self.epsilon > 0.0 && self.epsilon <= 0.01) {
+            return Err("glicko2.epsilon must be above 0 and at most 0.01".to_string());
+        }
+        if !self.initial_rating.is_finite() {
+            return Err("glicko2.initial_rating must be a number".to_string());
+        }
+        if !(self.initial_rd > 0.0 && self.initial_rd.is_finite()) {
+            return Err("glicko2.initial_rd must be a positive number".to_string());
+        }
+        if !(self.initial_volatility > 0.0 && self.initial_volatility < 1.0) {
+            return Err("glicko2.initial_volatility must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A rating system the scheduler can use to turn verdicts into ratings.
+///
+/// Every item stores its rating as a [`Glicko2`] triple (mu/phi/sigma); the
+/// engine decides what those numbers mean and how they are reported.
+pub trait RatingEngine: Send + Sync {
+    fn kind(&self) -> EngineKind;
+
+    fn initial_rating(&self) -> Glicko2;
+
+    /// Applies a single verdict between `r1` and `r2`, returning both new ratings.
+    fn rate(&self, r1: &Glicko2, r2: &Glicko2, winner: MatchWinner) -> (Glicko2, Glicko2);
+
+    /// Applies every game an item played in one rating period at once. `games`
+    /// pairs each opponent's pre-period rating with the item's score (1 win,
+    /// 0.5 tie, 0 loss, or anything in between) and may be empty.
+    fn rate_period(&self, rating: &Glicko2, games: &[(Glicko2, f64)]) -> Glicko2;
+
+    /// Converts a stored rating into the rating/rating deviation shown to users.
+    fn display(&self, rating: &Glicko2) -> Glicko1;
+
+    /// Gap between displayed ratings at which the higher rated item is expected
+    /// to win ten times as often as it loses. Glicko-2 always reports on the
+    /// Glicko-1 scale of 400.
+    fn scale(&self) -> f64 {
+        elo::algo::SCALE
+    }
+}
+
+/// An engine of the given kind with default parameters, for tests.
+#[cfg(test)]
+pub fn engine_for(kind: EngineKind) -> Arc<dyn RatingEngine> {
+    engine_with(kind, EloConfig::default(), Glicko2Config::default())
+}
+
+pub fn engine_with(
+    kind: EngineKind,
+    elo: EloConfig,
+    glicko2: Glicko2Config,
+) -> Arc<dyn RatingEngine> {
+    match kind {
+        EngineKind::Elo => Arc::new(EloEngine::new(elo)),
+        EngineKind::Glicko2 => Arc::new(Glicko2Engine::new(glicko2)),
+    }
+}
+
+/// Elo. `mu` holds the Elo rating directly and `phi` counts the verdicts the
+/// item has taken part in, which dynamic K is based on; `sigma` is unused.
+pub struct EloEngine {
+    config: EloConfig,
+}
+
+impl EloEngine {
+    pub fn new(config: EloConfig) -> Self {
+        EloEngine { config }
+    }
+
+    fn k(&self, rating: &Glicko2) -> f64 {
+        self.config.k_after(rating.phi)
+    }
+}
+
+impl RatingEngine for EloEngine {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Elo
+    }
+
+    fn initial_rating(&self) -> Glicko2 {
+        Glicko2 {
+            mu: self.config.initial_rating,
+            sigma: 0.0,
+            phi: 0.0,
+        }
+    }
+
+    fn rate(&self, r1: &Glicko2, r2: &Glicko2, winner: MatchWinner) -> (Glicko2, Glicko2) {
+        let w = match winner {
+            MatchWinner::A => elo::algo::Winner::P1,
+            MatchWinner::B => elo::algo::Winner::P2,
+            MatchWinner::Tie => elo::algo::Winner::Draw,
+            lean => elo::algo::Winner::Score(lean.score_a()),
+        };
+        let (mu1, mu2) =
+            elo::algo::calculate(r1.mu, r2.mu, self.k(r1), self.k(r2), self.config.scale, w);
+        (
+            Glicko2 {
+                mu: mu1,
+                phi: r1.phi + 1.0,
+                ..*r1
+            },
+            Glicko2 {
+                mu: mu2,
+                phi: r2.phi + 1.0,
+                ..*r2
+            },
+        )
+    }
+
+    fn rate_period(&self, rating: &Glicko2, games: &[(Glicko2, f64)]) -> Glicko2 {
+        let delta: f64 = games
+            .iter()
+            .map(|(op, score)| score - elo::algo::expected(rating.mu, op.mu, self.config.scale))
+            .sum();
+        Glicko2 {
+            mu: rating.mu + self.k(rating) * delta,
+            phi: rating.phi + games.len() as f64,
+            ..*rating
+        }
+    }
+
+    fn display(&self, rating: &Glicko2) -> Glicko1 {
+        Glicko1 {
+            rating: rating.mu,
+            sigma: 0.0,
+            rd: 0.0,
+        }
+    }
+
+    fn scale(&self) -> f64 {
+        self.config.scale
+    }
+}
+
+/// Glicko-2 applied one verdict at a time, each verdict treated as its own rating period.
+pub struct Glicko2Engine {
+    config: Glicko2Config,
+}
+
+impl Glicko2Engine {
+    pub fn new(config: Glicko2Config) -> Self {
+        Glicko2Engine { config }
+    }
+}
+
+impl RatingEngine for Glicko2Engine {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Glicko2
+    }
+
+    fn initial_rating(&self) -> Glicko2 {
+        Glicko2::from_glicko1_vars(
+            self.config.initial_rating,
+            self.config.initial_volatility,
+            self.config.initial_rd,
+        )
+    }
+
+    fn rate(&self, r1: &Glicko2, r2: &Glicko2, winner: MatchWinner) -> (Glicko2, Glicko2) {
+        let s1 = winner.score_a();
+        (
+            self.rate_period(r1, &[(*r2, s1)]),
+            self.rate_period(r2, &[(*r1, 1f64 - s1)]),
+        )
+    }
+
+    fn rate_period(&self, rating: &Glicko2, games: &[(Glicko2, f64)]) -> Glicko2 {
+        let mut next = *rating;
+        if games.is_empty() {
+            next.skip_period();
+            return next;
+        }
+        let opponents: Vec<&Glicko2> = games.iter().map(|(op, _)| op).collect();
+        let scores: Vec<f64> = games.iter().map(|(_, score)| *score).collect();
+        let constants = Constants {
+            tau: self.config.tau,
+            epsilon: self.config.epsilon,
+        };
+        if let Err(err) = next.process_matches(&opponents, &scores, &constants) {
+            tracing::warn!("{}, keeping the previous volatility", err);
+            next.process_matches_keeping_volatility(&opponents, &scores);
+        }
+        next
+    }
+
+    fn display(&self, rating: &Glicko2) -> Glicko1 {
+        Glicko1::from_glicko2(rating)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+
+    #[test]
+    #[timeout(100)]
+    fn test_engine_kind_parse() {
+        assert_eq!("elo".parse::<EngineKind>(), Ok(EngineKind::Elo));
+        assert_eq!("Glicko2".parse::<EngineKind>(), Ok(EngineKind::Glicko2));
+        assert!("trueskill".parse::<EngineKind>().is_err());
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_elo_winner_gains() {
+        let engine = EloEngine::new(EloConfig::default());
+        let start = engine.initial_rating();
+        let (a, b) = engine.rate(&start, &start, MatchWinner::A);
+        assert_eq!(engine.display(&a).rating, 1015.0);
+        assert_eq!(engine.display(&b).rating, 985.0);
+        assert_eq!((a.phi, b.phi), (1.0, 1.0));
+
+        // the favourite gains less for winning than the underdog would have
+        let (favourite, _) = engine.rate(&a, &b, MatchWinner::A);
+        let (underdog, _) = engine.rate(&b, &a, MatchWinner::A);
+        assert!(favourite.mu - a.mu < underdog.mu - b.mu);
+        assert!((favourite.mu - a.mu + underdog.mu - b.mu - elo::algo::K).abs() < 1e-9);
+
+        let (lean, _) = engine.rate(&start, &start, MatchWinner::LeanA);
+        assert_eq!(lean.mu, 1007.5);
+        let batched = engine.rate_period(&start, &[(start, 1.0), (start, 0.0)]);
+        assert_eq!((batched.mu, batched.phi), (1000.0, 2.0));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_elo_dynamic_k() {
+        let config = EloConfig {
+            k: 40.0,
+            min_k: Some(10.0),
+            k_half_life: 5.0,
+            initial_rating: 1500.0,
+            ..EloConfig::default()
+        };
+        assert_eq!(config.k_after(0.0), 40.0);
+        assert_eq!(config.k_after(5.0), 25.0);
+        assert!((config.k_after(1000.0) - 10.0).abs() < 1e-9);
+        assert_eq!(EloConfig::default().k_after(1000.0), elo::algo::K);
+
+        let engine = EloEngine::new(config);
+        let mut a = engine.initial_rating();
+        let mut b = engine.initial_rating();
+        assert_eq!(a.mu, 1500.0);
+        let mut gains = vec![];
+        for _ in 0..10 {
+            let (tie_a, tie_b) = engine.rate(&a, &b, MatchWinner::Tie);
+            let (won, _) = engine.rate(&tie_a, &tie_b, MatchWinner::A);
+            gains.push(won.mu - tie_a.mu);
+            (a, b) = (tie_a, tie_b);
+        }
+        assert!(gains.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_elo_config_validation() {
+        assert!(EloConfig::default().validate().is_ok());
+        for invalid in [
+            EloConfig {
+                k: 0.0,
+                ..EloConfig::default()
+            },
+            EloConfig {
+                scale: -400.0,
+                ..EloConfig::default()
+            },
+            EloConfig {
+                initial_rating: f64::NAN,
+                ..EloConfig::default()
+            },
+            EloConfig {
+                min_k: Some(50.0),
+                ..EloConfig::default()
+            },
+            EloConfig {
+                min_k: Some(10.0),
+                k_half_life: 0.0,
+                ..EloConfig::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_glicko2_config() {
+        let default = Glicko2Engine::new(Glicko2Config::default());
+        let start = default.display(&default.initial_rating());
+        assert!((start.rating - 1500.0).abs() < 1e-9);
+        assert!((start.rd - 350.0).abs() < 1e-9);
+        assert_eq!(start.sigma, 0.06);
+
+        let custom = Glicko2Engine::new(Glicko2Config {
+            initial_rating: 1200.0,
+            initial_rd: 200.0,
+            ..Glicko2Config::default()
+        });
+        let start = custom.display(&custom.initial_rating());
+        assert!((start.rating - 1200.0).abs() < 1e-9);
+        assert!((start.rd - 200.0).abs() < 1e-9);
+
+        // a settled item losing to a much weaker one: volatility reacts more
+        // the higher tau is
+        let settled = Glicko2::from_glicko1_vars(1800.0, 0.06, 50.0);
+        let weak = Glicko2::from_glicko1_vars(1200.0, 0.06, 50.0);
+        let volatility = |tau: f64| {
+            let engine = Glicko2Engine::new(Glicko2Config {
+                tau,
+                ..Glicko2Config::default()
+            });
+            engine.rate_period(&settled, &[(weak, 0.0)]).sigma
+        };
+        assert!(volatility(0.3) < volatility(1.2));
+
+        assert!(Glicko2Config::default().validate().is_ok());
+        for invalid in [
+            Glicko2Config {
+                tau: 0.0,
+                ..Glicko2Config::default()
+            },
+            Glicko2Config {
+                epsilon: 0.5,
+                ..Glicko2Config::default()
+            },
+            Glicko2Config {
+                initial_rd: 0.0,
+                ..Glicko2Config::default()
+            },
+            Glicko2Config {
+                initial_volatility: 1.5,
+                ..Glicko2Config::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_glicko2_winner_gains() {
+        let engine = Glicko2Engine::new(Glicko2Config::default());
+        let start = engine.initial_rating();
+        let (a, b) = engine.rate(&start, &start, MatchWinner::A);
+
+        let a1 = engine.display(&a);
+        let b1 = engine.display(&b);
+        assert!(a1.rating > 1500.0);
+        assert!(b1.rating < 1500.0);
+        assert!(a1.rd < 350.0);
+        assert!((a1.rating - 1500.0 + b1.rating - 1500.0).abs() < 0.01);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_glicko2_empty_period_inflates_rd() {
+        let engine = Glicko2Engine::new(Glicko2Config::default());
+        let start = Glicko2::from_glicko1_vars(1500.0, 0.06, 50.0);
+        let next = engine.rate_period(&start, &[]);
+
+        assert_eq!(next.mu, start.mu);
+        assert!(next.phi > start.phi);
+        assert!((next.phi - (start.phi.powi(2) + 0.06f64.powi(2)).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_glicko2_period_differs_from_sequential() {
+        let engine = Glicko2Engine::new(Glicko2Config::default());
+        let me = engine.initial_rating();
+        let o1 = Glicko2::from_glicko1_vars(1400.0, 0.06, 30.0);
+        let o2 = Glicko2::from_glicko1_vars(1550.0, 0.06, 100.0);
+
+        let batched = engine.rate_period(&me, &[(o1, 1.0), (o2, 0.0)]);
+        let first = engine.rate_period(&me, &[(o1, 1.0)]);
+        let sequential = engine.rate_period(&first, &[(o2, 0.0)]);
+
+        assert!(batched.phi < me.phi);
+        assert_ne!(batched, sequential);
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_glicko2_tie_and_lean() {
+        let engine = Glicko2Engine::new(Glicko2Config::default());
+        let start = engine.initial_rating();
+        let (a, b) = engine.rate(&start, &start, MatchWinner::Tie);
+        assert!((a.mu - start.mu).abs() < 1e-12);
+        assert!((b.mu - start.mu).abs() < 1e-12);
+        assert!(a.phi < start.phi);
+
+        let (lean, _) = engine.rate(&start, &start, MatchWinner::LeanA);
+        let (win, _) = engine.rate(&start, &start, MatchWinner::A);
+        assert!(start.mu < lean.mu && lean.mu < win.mu);
+    }
+}
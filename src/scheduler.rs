@@ -1,40 +1,24 @@
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use priority_queue::DoublePriorityQueue;
-use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
-    error::Error,
-    fmt,
-    sync::{Arc, RwLock},
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
     vec,
 };
 
-use crate::elo;
-
-#[derive(Debug)]
-pub struct SchedulerError {
-    details: String,
-}
-
-impl SchedulerError {
-    pub fn new(msg: &str) -> SchedulerError {
-        SchedulerError {
-            details: msg.to_string(),
-        }
-    }
-}
-
-impl fmt::Display for SchedulerError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
-    }
-}
-
-impl Error for SchedulerError {
-    fn description(&self) -> &str {
-        &self.details
-    }
-}
+use crate::{
+    auth,
+    error::SchedulerError,
+    glicko2::algo::Glicko2,
+    leaderboard::{self, LeaderboardEntry, RankingMethod},
+    pairing::{self, PairSelector, PairingKind},
+    rating::{RatingEngine, RatingMode},
+    storage::Storage,
+    votelog::{self, Vote},
+};
 
 #[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Item {
@@ -43,6 +27,11 @@ pub struct Item {
     pub location: String,
     pub description: String,
     pub score: f64,
+    pub rd: f64,
+    pub rating: Glicko2,
+    /// Cleared when the item is withdrawn. Withdrawn items are no longer paired
+    /// or ranked, but their verdicts stay in the log.
+    pub active: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,24 +39,114 @@ pub struct MatchPair {
     pub match_pair_id: String,
     pub i1: String,
     pub i2: String,
-    visit_count: i32,
-    winner: Option<MatchWinner>,
-    judge_id: Option<String>,
+    pub visit_count: i32,
+    pub winner: Option<MatchWinner>,
+    pub judge_id: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct Judge {
-    id: String,
-    email: String,
+    pub id: String,
+    pub email: String,
+    /// Cleared when the judge is removed from the panel, which stops them
+    /// from being assigned or casting verdicts.
+    pub active: bool,
+}
+
+/// Changes to an item; fields left out are kept.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemUpdate {
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub active: Option<bool>,
+}
+
+/// Changes to a judge; fields left out are kept.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JudgeUpdate {
+    pub email: Option<String>,
+    pub active: Option<bool>,
+}
+
+/// Names that differ only in case or spacing count as the same item.
+pub fn name_key(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+pub fn valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+                && !email.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+fn check_email(email: &str) -> Result<String, SchedulerError> {
+    let email = email.trim().to_string();
+    if !valid_email(&email) {
+        return Err(SchedulerError::Validation(format!(
+            "invalid email {}",
+            email
+        )));
+    }
+    Ok(email)
+}
+
+/// Emails are compared ignoring case; `judge_id` may keep its own.
+fn check_email_free(
+    judges: &[Judge],
+    email: &str,
+    judge_id: Option<&str>,
+) -> Result<(), SchedulerError> {
+    if judges
+        .iter()
+        .any(|j| Some(j.id.as_str()) != judge_id && j.email.eq_ignore_ascii_case(email))
+    {
+        return Err(SchedulerError::Conflict(format!(
+            "a judge with email {} already exists",
+            email
+        )));
+    }
+    Ok(())
 }
 
+/// A judge's verdict on a match, from a clear preference for one item through
+/// a slight preference to no preference at all.
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MatchWinner {
     A,
     B,
+    Tie,
+    LeanA,
+    LeanB,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+impl MatchWinner {
+    /// The score item A receives for this verdict; item B receives the rest.
+    pub fn score_a(&self) -> f64 {
+        match self {
+            MatchWinner::A => 1f64,
+            MatchWinner::LeanA => 0.75,
+            MatchWinner::Tie => 0.5,
+            MatchWinner::LeanB => 0.25,
+            MatchWinner::B => 0f64,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
 pub enum States {
     NoState,
     Init,
@@ -75,23 +154,205 @@ pub enum States {
     End,
 }
 
+/// Tunables fixed for the lifetime of a `SchedulerState`.
+#[derive(Clone, Debug)]
+pub struct SchedulerOptions {
+    pub rating_mode: RatingMode,
+    pub pairing: PairingKind,
+    /// How many times a single judge may be shown the same item, `None` for no limit.
+    pub max_item_views: Option<u32>,
+    /// How long a judge holds a match before it is handed to someone else.
+    pub lease_timeout: Duration,
+    /// Rounds of seed matches used when matchmaking is started without a count.
+    pub seed_rounds: usize,
+    /// Seeds the generator behind seed matches and pairing so that the same
+    /// sequence of requests yields the same matches; `None` seeds from the OS.
+    pub seed: Option<u64>,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        SchedulerOptions {
+            rating_mode: RatingMode::Immediate,
+            pairing: PairingKind::InformationGain,
+            max_item_views: Some(3),
+            lease_timeout: Duration::from_secs(10 * 60),
+            seed_rounds: 2,
+            seed: None,
+        }
+    }
+}
+
+/// A judge's claim on a match they were assigned but have not judged yet.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Lease {
+    pub match_id: String,
+    pub judge_id: String,
+    pub expires_at_ms: u64,
+}
+
+impl Lease {
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        now_ms >= self.expires_at_ms
+    }
+}
+
+/// Why a judge passed on a match instead of judging it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// One of the items could not be found at its location.
+    ItemAbsent,
+    ConflictOfInterest,
+    /// The judge does not know the domain well enough to compare the items.
+    Unfamiliar,
+    Other,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::ItemAbsent => "item_absent",
+            SkipReason::ConflictOfInterest => "conflict_of_interest",
+            SkipReason::Unfamiliar => "unfamiliar",
+            SkipReason::Other => "other",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<SkipReason> {
+        match s {
+            "item_absent" => Some(SkipReason::ItemAbsent),
+            "conflict_of_interest" => Some(SkipReason::ConflictOfInterest),
+            "unfamiliar" => Some(SkipReason::Unfamiliar),
+            "other" => Some(SkipReason::Other),
+            _ => None,
+        }
+    }
+}
+
+/// Who an item stops being shown to after a skip.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnavailableScope {
+    /// Only the judge who skipped, e.g. for a conflict of interest.
+    Judge,
+    /// Every judge, e.g. because the team has left.
+    Global,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ItemUnavailable {
+    pub item_id: String,
+    pub scope: UnavailableScope,
+}
+
+/// A judge passing on a match they were leased. Ratings are not affected.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Skip {
+    pub judge_id: String,
+    pub match_id: String,
+    pub reason: SkipReason,
+    pub unavailable: Option<ItemUnavailable>,
+    pub timestamp_ms: u64,
+}
+
+/// Everything a single judge has been assigned so far.
+#[derive(Clone, Debug, Default)]
+pub struct JudgeHistory {
+    pairs: HashSet<(String, String)>,
+    item_views: HashMap<String, u32>,
+    /// Items this judge asked not to be shown again.
+    unavailable: HashSet<String>,
+}
+
+impl JudgeHistory {
+    pub fn record(&mut self, i1: &str, i2: &str) {
+        self.pairs.insert(pairing::pair_key(i1, i2));
+        *self.item_views.entry(i1.to_string()).or_insert(0) += 1;
+        *self.item_views.entry(i2.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn views(&self, item_id: &str) -> u32 {
+        self.item_views.get(item_id).copied().unwrap_or(0)
+    }
+
+    fn can_see_item(&self, item_id: &str, max_item_views: Option<u32>) -> bool {
+        !self.unavailable.contains(item_id)
+            && max_item_views.is_none_or(|max| self.views(item_id) < max)
+    }
+
+    /// Whether a judge with this history may be given a match between `i1` and `i2`.
+    pub fn allows(&self, i1: &str, i2: &str, max_item_views: Option<u32>) -> bool {
+        !self.pairs.contains(&pairing::pair_key(i1, i2))
+            && self.can_see_item(i1, max_item_views)
+            && self.can_see_item(i2, max_item_views)
+    }
+}
+
 #[derive(Clone)]
 pub struct SchedulerState {
     current_state: Arc<RwLock<States>>,
+    paused: Arc<RwLock<bool>>,
     judges: Arc<RwLock<Vec<Judge>>>,
-    items: Arc<DashMap<String, Box<Item>>>,
+    /// Hashes of the tokens judges authenticate with, mapped to the judge's id.
+    judge_tokens: Arc<DashMap<String, String>>,
+    items: Arc<DashMap<String, Item>>,
+    /// Held from checking that an item name is free until the item is stored,
+    /// so no two items end up with the same name.
+    item_names: Arc<Mutex<()>>,
     matches: Arc<DashMap<String, Arc<MatchPair>>>,
     mq: Arc<RwLock<DoublePriorityQueue<String, i32>>>,
+    votes: Arc<RwLock<Vec<Vote>>>,
+    rating_period: Arc<RwLock<u64>>,
+    judge_history: Arc<DashMap<String, JudgeHistory>>,
+    leases: Arc<DashMap<String, Lease>>,
+    skips: Arc<RwLock<Vec<Skip>>>,
+    /// Items no judge should be shown any more.
+    unavailable: Arc<RwLock<HashSet<String>>>,
+    engine: Arc<dyn RatingEngine>,
+    selector: Arc<dyn PairSelector>,
+    rng: Arc<Mutex<StdRng>>,
+    options: SchedulerOptions,
+    storage: Option<Arc<Storage>>,
+    leaderboards: Arc<Mutex<HashMap<(RankingMethod, bool), CachedLeaderboard>>>,
+}
+
+/// A leaderboard and the state it was computed from.
+struct CachedLeaderboard {
+    logged: usize,
+    period: u64,
+    items: Vec<Item>,
+    entries: Vec<LeaderboardEntry>,
+}
+
+impl States {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            States::NoState => "NoState",
+            States::Init => "Init",
+            States::Continuous => "Continuous",
+            States::End => "End",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<States> {
+        match s {
+            "NoState" => Some(States::NoState),
+            "Init" => Some(States::Init),
+            "Continuous" => Some(States::Continuous),
+            "End" => Some(States::End),
+            _ => None,
+        }
+    }
 }
 
-fn create_initial_matches(competitors: &[Box<Item>], n: usize) -> Vec<MatchPair> {
+fn create_initial_matches(competitors: &[Item], n: usize, rng: &mut StdRng) -> Vec<MatchPair> {
     let mut matches: Vec<MatchPair> = vec![];
-    let rng = &mut rand::thread_rng();
     for _ in 0..n {
-        let mut cc: Vec<&Box<Item>> = Vec::from_iter(competitors);
+        let mut cc: Vec<&Item> = Vec::from_iter(competitors);
         cc.shuffle(rng);
-        if cc.len() % 2 != 0 {
-            cc.insert(0, cc.get(0).unwrap());
+        if !cc.len().is_multiple_of(2) {
+            cc.insert(0, cc[0]);
         }
         for i in 0..(cc.len() / 2) {
             let c1 = *cc.get(i).unwrap();
@@ -110,93 +371,698 @@ fn create_initial_matches(competitors: &[Box<Item>], n: usize) -> Vec<MatchPair>
 }
 
 impl Item {
-    pub fn new(name: String, location: String, description: String) -> Self {
-        Self {
+    pub fn new(
+        name: String,
+        location: String,
+        description: String,
+        engine: &dyn RatingEngine,
+    ) -> Self {
+        let mut item = Self {
             id: uuid::Uuid::new_v4().to_string(),
             name,
             location,
             description,
-            score: elo::algo::INITIAL_ELO,
-        }
+            score: 0.0,
+            rd: 0.0,
+            rating: engine.initial_rating(),
+            active: true,
+        };
+        item.set_rating(item.rating, engine);
+        item
+    }
+
+    pub fn set_rating(&mut self, rating: Glicko2, engine: &dyn RatingEngine) {
+        let shown = engine.display(&rating);
+        self.rating = rating;
+        self.score = shown.rating;
+        self.rd = shown.rd;
     }
 }
 
 impl Judge {
     pub fn new(email: String) -> Self {
-        Self {
-            id: uuid::Uuid::new_v4().to_string(),
-            email,
-        }
+        Self::from_id(email, uuid::Uuid::new_v4().to_string())
     }
 
     pub fn from_id(email: String, id: String) -> Self {
-        Self { id, email }
-    }
-
-    pub fn log_match_action(&self) {
-        println!("match logged");
+        Self {
+            id,
+            email,
+            active: true,
+        }
     }
 }
 
 impl SchedulerState {
-    pub fn new() -> SchedulerState {
+    pub fn new(engine: Arc<dyn RatingEngine>, options: SchedulerOptions) -> SchedulerState {
         let current_state = Arc::from(RwLock::from(States::NoState));
         let judges = Arc::from(RwLock::from(vec![]));
         let items = Arc::from(DashMap::new());
         let matches = Arc::from(DashMap::new());
         let mq = Arc::from(RwLock::from(DoublePriorityQueue::new()));
+        let votes = Arc::from(RwLock::from(vec![]));
+        let rating_period = Arc::from(RwLock::from(0));
+        let selector = pairing::selector_for(options.pairing, engine.scale());
+        let rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
         SchedulerState {
             current_state,
+            paused: Arc::from(RwLock::from(false)),
             judges,
+            judge_tokens: Arc::from(DashMap::new()),
             items,
+            item_names: Arc::from(Mutex::new(())),
             matches,
             mq,
+            votes,
+            rating_period,
+            judge_history: Arc::from(DashMap::new()),
+            leases: Arc::from(DashMap::new()),
+            skips: Arc::from(RwLock::from(vec![])),
+            unavailable: Arc::from(RwLock::from(HashSet::new())),
+            engine,
+            selector,
+            rng: Arc::from(Mutex::new(rng)),
+            options,
+            storage: None,
+            leaderboards: Arc::from(Mutex::new(HashMap::new())),
         }
     }
 
-    pub fn judge_match(&self, judge: &Judge, match_id: &str, winner: MatchWinner) -> bool {
+    /// Rebuilds a scheduler from everything previously written to `storage`,
+    /// and keeps writing every change back to it.
+    pub fn load(
+        engine: Arc<dyn RatingEngine>,
+        options: SchedulerOptions,
+        storage: Arc<Storage>,
+    ) -> rusqlite::Result<SchedulerState> {
+        let snapshot = storage.load()?;
+        let mut state = SchedulerState::new(engine, options);
+
+        *state.current_state.write().unwrap() = snapshot.state;
+        *state.paused.write().unwrap() = snapshot.paused;
+        *state.judges.write().unwrap() = snapshot.judges;
+        for (token_hash, judge_id) in snapshot.judge_tokens {
+            state.judge_tokens.insert(token_hash, judge_id);
+        }
+        for mut item in snapshot.items {
+            item.set_rating(item.rating, state.engine.as_ref());
+            state.items.insert(item.id.clone(), item);
+        }
+        {
+            let mut pq = state.mq.write().unwrap();
+            for (m, queued) in snapshot.matches {
+                if queued {
+                    pq.push(m.match_pair_id.clone(), m.visit_count);
+                }
+                state.matches.insert(m.match_pair_id.clone(), Arc::from(m));
+            }
+        }
+        *state.votes.write().unwrap() = snapshot.votes;
+        *state.rating_period.write().unwrap() = snapshot.rating_period;
+        for (judge_id, i1, i2) in snapshot.judge_history {
+            state
+                .judge_history
+                .entry(judge_id)
+                .or_default()
+                .record(&i1, &i2);
+        }
+        for lease in snapshot.leases {
+            state.leases.insert(lease.match_id.clone(), lease);
+        }
+        for skip in snapshot.skips {
+            state.mark_unavailable(&skip);
+            state.skips.write().unwrap().push(skip);
+        }
+
+        state.storage = Some(storage);
+        Ok(state)
+    }
+
+    pub fn engine(&self) -> Arc<dyn RatingEngine> {
+        self.engine.clone()
+    }
+
+    pub fn options(&self) -> &SchedulerOptions {
+        &self.options
+    }
+
+    fn persist<F>(&self, write: F)
+    where
+        F: FnOnce(&Storage) -> rusqlite::Result<()>,
+    {
+        if let Some(storage) = &self.storage {
+            if let Err(err) = write(storage) {
+                tracing::error!("failed to persist scheduler state: {}", err);
+            }
+        }
+    }
+
+    /// Stores a verdict along with everything it changed, see
+    /// [`Storage::record_verdict`]. Unlike other writes a failure is returned,
+    /// since a verdict that was not stored must not count.
+    fn persist_verdict(
+        &self,
+        vote: &Vote,
+        m: &MatchPair,
+        rated: &[Item],
+    ) -> Result<(), SchedulerError> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        storage
+            .record_verdict(vote, m, self.is_queued(&m.match_pair_id), rated)
+            .map_err(|err| SchedulerError::Internal(format!("failed to store verdict: {}", err)))
+    }
+
+    fn persist_match(&self, m: &MatchPair, queued: bool) {
+        self.persist(|s| s.save_match(m, queued));
+    }
+
+    pub(crate) fn is_queued(&self, match_id: &str) -> bool {
+        self.mq.read().unwrap().get(match_id).is_some()
+    }
+
+    pub fn judge_match(
+        &self,
+        judge: &Judge,
+        match_id: &str,
+        winner: MatchWinner,
+        amend: bool,
+    ) -> Result<(), SchedulerError> {
         let matches = self.get_matches();
         let match_pair = match matches.get(match_id) {
             Some(data) => data.clone(),
-            None => return false,
+            None => {
+                return Err(SchedulerError::NotFound(format!(
+                    "match {} does not exist",
+                    match_id
+                )))
+            }
         };
+        self.check_judge(judge)?;
 
-        {
-            let new = MatchPair {
-                match_pair_id: match_pair.match_pair_id.clone(),
-                i1: match_pair.i1.clone(),
-                i2: match_pair.i2.clone(),
-                visit_count: match_pair.visit_count,
-                winner: Some(winner),
-                judge_id: match_pair.judge_id.clone(),
-            };
-            matches.insert(new.match_pair_id.clone(), new.into());
+        // holding the log lock for the whole update keeps the log order identical
+        // to the order ratings were changed in, which is what makes replay exact
+        let mut votes = self.votes.write().unwrap();
+        if self.get_state() == States::End {
+            return Err(SchedulerError::InvalidState(
+                "the event has ended, rankings are frozen".to_string(),
+            ));
         }
-
-        judge.log_match_action();
         let binding = self.items.clone();
-        let mut s1 = binding.get_mut(&match_pair.i1).unwrap();
-        let mut s2 = binding.get_mut(&match_pair.i2).unwrap();
-        return match winner {
-            MatchWinner::A => {
-                let s1_elo = s1.score;
-                let s2_elo = s2.score;
-                let (r1, r2) =
-                    elo::algo::calculate(s1_elo, s2_elo, elo::algo::K, elo::algo::Winner::P1);
-                s1.score = r1;
-                s2.score = r2;
-                true
-            }
-            MatchWinner::B => {
-                let s1_elo = s1.score;
-                let s2_elo = s2.score;
-                let (r1, r2) =
-                    elo::algo::calculate(s1_elo, s2_elo, elo::algo::K, elo::algo::Winner::P2);
-                s1.score = r1;
-                s2.score = r2;
-                true
+        // read both ratings before taking write guards, both items may live in the same shard
+        let (r1, r2) = match (binding.get(&match_pair.i1), binding.get(&match_pair.i2)) {
+            (Some(s1), Some(s2)) => (s1.rating, s2.rating),
+            _ => {
+                return Err(SchedulerError::Internal(format!(
+                    "match {} refers to an item that does not exist",
+                    match_id
+                )))
+            }
+        };
+
+        let previous = votelog::effective(&votes)
+            .into_iter()
+            .find(|v| v.match_id == match_id)
+            .cloned();
+        let lease = match &previous {
+            Some(previous) => {
+                if !amend {
+                    return Err(SchedulerError::Conflict(
+                        "match has already been judged, set `amend` to replace the verdict"
+                            .to_string(),
+                    ));
+                }
+                if previous.judge_id != judge.id {
+                    return Err(SchedulerError::Forbidden(
+                        "only the judge who cast a verdict can amend it".to_string(),
+                    ));
+                }
+                None
+            }
+            None => {
+                let now = votelog::now_ms();
+                let removed = self.leases.remove_if(match_id, |_, lease| {
+                    lease.judge_id == judge.id && !lease.is_expired(now)
+                });
+                match removed {
+                    Some((_, lease)) => Some(lease),
+                    None => {
+                        return Err(SchedulerError::Forbidden(
+                            "judge does not hold the lease on this match".to_string(),
+                        ))
+                    }
+                }
+            }
+        };
+
+        let judged = MatchPair {
+            match_pair_id: match_pair.match_pair_id.clone(),
+            i1: match_pair.i1.clone(),
+            i2: match_pair.i2.clone(),
+            visit_count: match_pair.visit_count,
+            winner: Some(winner),
+            judge_id: match_pair.judge_id.clone(),
+        };
+
+        let (n1, n2) = match (self.options.rating_mode, &previous) {
+            (RatingMode::Immediate, None) => self.engine.rate(&r1, &r2, winner),
+            // applied in bulk by close_rating_period, or by the replay below
+            _ => (r1, r2),
+        };
+
+        let mut vote = Vote {
+            seq: votes.len() as u64,
+            judge_id: judge.id.clone(),
+            match_id: match_pair.match_pair_id.clone(),
+            i1: match_pair.i1.clone(),
+            i2: match_pair.i2.clone(),
+            winner,
+            timestamp_ms: votelog::now_ms(),
+            period: match &previous {
+                Some(previous) => previous.period,
+                None => *self.rating_period.read().unwrap(),
+            },
+            pre_i1: r1,
+            pre_i2: r2,
+            post_i1: n1,
+            post_i2: n2,
+            amends: previous.as_ref().map(|v| v.seq),
+        };
+
+        let ratings: HashMap<String, Glicko2> = match (&previous, self.options.rating_mode) {
+            (Some(_), _) => {
+                // the amended vote may be anywhere in history, so rebuild every rating
+                votes.push(vote.clone());
+                let ratings = self.recompute_ratings(&votes);
+                votes.pop();
+                vote.post_i1 = ratings.get(&vote.i1).copied().unwrap_or(r1);
+                vote.post_i2 = ratings.get(&vote.i2).copied().unwrap_or(r2);
+                ratings
+            }
+            (None, RatingMode::Period) => HashMap::new(),
+            (None, RatingMode::Immediate) => {
+                HashMap::from([(match_pair.i1.clone(), n1), (match_pair.i2.clone(), n2)])
+            }
+        };
+        let rated: Vec<Item> = ratings
+            .iter()
+            .filter_map(|(id, &rating)| {
+                let mut item = binding.get(id)?.clone();
+                if item.rating == rating {
+                    return None;
+                }
+                item.set_rating(rating, self.engine.as_ref());
+                Some(item)
+            })
+            .collect();
+
+        // nothing changes in memory unless the whole verdict was stored
+        if let Err(err) = self.persist_verdict(&vote, &judged, &rated) {
+            if let Some(lease) = lease {
+                self.leases.insert(lease.match_id.clone(), lease);
+            }
+            return Err(err);
+        }
+        matches.insert(judged.match_pair_id.clone(), judged.into());
+        for item in rated {
+            if let Some(mut current) = binding.get_mut(&item.id) {
+                current.set_rating(item.rating, self.engine.as_ref());
+            }
+        }
+        if let Some(amends) = vote.amends {
+            tracing::info!(
+                "judge {} amended vote {} on match {}",
+                judge.id,
+                amends,
+                match_id
+            );
+        }
+        votes.push(vote);
+        Ok(())
+    }
+
+    /// Only judges still on the panel may cast verdicts or skip.
+    fn check_judge(&self, judge: &Judge) -> Result<(), SchedulerError> {
+        match self.get_judge(&judge.id) {
+            Some(known) if known.email == judge.email => match known.active {
+                true => Ok(()),
+                false => Err(SchedulerError::Forbidden(
+                    "judge has been deactivated".to_string(),
+                )),
+            },
+            _ => Err(SchedulerError::Unauthorized(
+                "judge does not exist".to_string(),
+            )),
+        }
+    }
+
+    pub fn get_leases(&self) -> Vec<Lease> {
+        self.leases.iter().map(|l| l.clone()).collect()
+    }
+
+    /// Drops every lease that has run out and puts its match back in the queue
+    /// as if it had never been handed out. Returns how many were released.
+    pub fn release_expired_leases(&self) -> usize {
+        let now = votelog::now_ms();
+        let expired: Vec<Lease> = self
+            .leases
+            .iter()
+            .filter(|l| l.is_expired(now))
+            .map(|l| l.clone())
+            .collect();
+
+        for lease in &expired {
+            self.release(lease);
+            tracing::info!(
+                "lease on match {} held by judge {} expired",
+                lease.match_id,
+                lease.judge_id
+            );
+        }
+        expired.len()
+    }
+
+    fn release(&self, lease: &Lease) {
+        self.leases.remove(&lease.match_id);
+        self.persist(|s| s.delete_lease(&lease.match_id));
+        self.requeue(&lease.match_id);
+    }
+
+    /// Puts a match back in the queue as if it had never been handed out,
+    /// unless one of its items has been withdrawn in the meantime.
+    fn requeue(&self, match_id: &str) {
+        let m = match self.matches.get(match_id) {
+            Some(m) => m.clone(),
+            None => return,
+        };
+        let released = MatchPair {
+            match_pair_id: m.match_pair_id.clone(),
+            i1: m.i1.clone(),
+            i2: m.i2.clone(),
+            visit_count: (m.visit_count - 1).max(0),
+            winner: m.winner,
+            judge_id: None,
+        };
+        let queued = self.is_active(&released.i1) && self.is_active(&released.i2);
+        if queued {
+            self.mq
+                .write()
+                .unwrap()
+                .push(released.match_pair_id.clone(), released.visit_count);
+        }
+        self.persist_match(&released, queued);
+        self.matches
+            .insert(released.match_pair_id.clone(), Arc::new(released));
+    }
+
+    /// Lets a judge pass on a match they hold without judging it. The match
+    /// goes back in the queue for someone else and ratings are left alone.
+    pub fn skip_match(
+        &self,
+        judge: &Judge,
+        match_id: &str,
+        reason: SkipReason,
+        unavailable: Option<ItemUnavailable>,
+    ) -> Result<(), SchedulerError> {
+        let match_pair = match self.matches.get(match_id) {
+            Some(m) => m.clone(),
+            None => {
+                return Err(SchedulerError::NotFound(format!(
+                    "match {} does not exist",
+                    match_id
+                )))
+            }
+        };
+        self.check_judge(judge)?;
+        if let Some(u) = &unavailable {
+            if u.item_id != match_pair.i1 && u.item_id != match_pair.i2 {
+                return Err(SchedulerError::Validation(format!(
+                    "item {} is not part of match {}",
+                    u.item_id, match_id
+                )));
             }
+        }
+
+        let votes = self.votes.read().unwrap();
+        if self.get_state() == States::End {
+            return Err(SchedulerError::InvalidState(
+                "the event has ended, rankings are frozen".to_string(),
+            ));
+        }
+        if votes.iter().any(|v| v.match_id == match_id) {
+            return Err(SchedulerError::Conflict(
+                "match has already been judged".to_string(),
+            ));
+        }
+        let now = votelog::now_ms();
+        let removed = self.leases.remove_if(match_id, |_, lease| {
+            lease.judge_id == judge.id && !lease.is_expired(now)
+        });
+        if removed.is_none() {
+            return Err(SchedulerError::Forbidden(
+                "judge does not hold the lease on this match".to_string(),
+            ));
+        }
+        self.persist(|s| s.delete_lease(match_id));
+        self.requeue(match_id);
+
+        let skip = Skip {
+            judge_id: judge.id.clone(),
+            match_id: match_id.to_string(),
+            reason,
+            unavailable,
+            timestamp_ms: now,
         };
+        self.mark_unavailable(&skip);
+        self.persist(|s| s.append_skip(&skip));
+        tracing::info!(
+            "judge {} skipped match {} ({})",
+            judge.id,
+            match_id,
+            reason.as_str()
+        );
+        self.skips.write().unwrap().push(skip);
+        Ok(())
+    }
+
+    fn mark_unavailable(&self, skip: &Skip) {
+        let Some(u) = &skip.unavailable else {
+            return;
+        };
+        match u.scope {
+            UnavailableScope::Judge => {
+                self.judge_history
+                    .entry(skip.judge_id.clone())
+                    .or_default()
+                    .unavailable
+                    .insert(u.item_id.clone());
+            }
+            UnavailableScope::Global => {
+                self.unavailable.write().unwrap().insert(u.item_id.clone());
+            }
+        }
+    }
+
+    pub fn get_skips(&self) -> Vec<Skip> {
+        self.skips.read().unwrap().clone()
+    }
+
+    fn is_active(&self, item_id: &str) -> bool {
+        self.items.get(item_id).is_some_and(|item| item.active)
+    }
+
+    fn is_available(&self, item_id: &str) -> bool {
+        self.is_active(item_id) && !self.unavailable.read().unwrap().contains(item_id)
+    }
+
+    /// Ranks the active items. Verdicts against withdrawn items still count
+    /// unless `exclude_inactive` is set, in which case they are dropped and
+    /// live ratings are replayed without them.
+    ///
+    /// The vote log is only locked long enough to copy it, and the result is
+    /// kept until the next verdict, period close or item change.
+    pub fn leaderboard(
+        &self,
+        method: RankingMethod,
+        exclude_inactive: bool,
+    ) -> Vec<LeaderboardEntry> {
+        let (votes, logged, period, items) = {
+            let votes = self.votes.read().unwrap();
+            let effective: Vec<Vote> = votelog::effective(&votes).into_iter().cloned().collect();
+            (
+                effective,
+                votes.len(),
+                self.get_rating_period(),
+                self.get_items(),
+            )
+        };
+        let key = (method, exclude_inactive);
+        if let Some(cached) = self.leaderboards.lock().unwrap().get(&key) {
+            if cached.logged == logged && cached.period == period && cached.items == items {
+                return cached.entries.clone();
+            }
+        }
+
+        let entries =
+            self.build_leaderboard(items.clone(), votes, period, method, exclude_inactive);
+        self.leaderboards.lock().unwrap().insert(
+            key,
+            CachedLeaderboard {
+                logged,
+                period,
+                items,
+                entries: entries.clone(),
+            },
+        );
+        entries
+    }
+
+    fn build_leaderboard(
+        &self,
+        items: Vec<Item>,
+        votes: Vec<Vote>,
+        period: u64,
+        method: RankingMethod,
+        exclude_inactive: bool,
+    ) -> Vec<LeaderboardEntry> {
+        if !exclude_inactive {
+            return leaderboard::build(
+                &items,
+                &votes,
+                self.engine.as_ref(),
+                self.options.rating_mode,
+                period,
+                method,
+            );
+        }
+
+        let mut items: Vec<Item> = items.into_iter().filter(|i| i.active).collect();
+        let active: HashSet<&str> = items.iter().map(|i| i.id.as_str()).collect();
+        let votes: Vec<Vote> = votes
+            .into_iter()
+            .filter(|v| active.contains(v.i1.as_str()) && active.contains(v.i2.as_str()))
+            .collect();
+        let ratings = votelog::replay(
+            active.iter().map(|id| id.to_string()),
+            &votes,
+            self.engine.as_ref(),
+            self.options.rating_mode,
+            period,
+        );
+        for item in &mut items {
+            if let Some(&rating) = ratings.get(&item.id) {
+                item.set_rating(rating, self.engine.as_ref());
+            }
+        }
+        leaderboard::build(
+            &items,
+            &votes,
+            self.engine.as_ref(),
+            self.options.rating_mode,
+            period,
+            method,
+        )
+    }
+
+    pub fn get_votes(&self) -> Vec<Vote> {
+        self.votes.read().unwrap().clone()
+    }
+
+    /// Throws away the current ratings and rebuilds them by replaying the vote log.
+    pub fn replay_votes(&self) -> Vec<Item> {
+        let votes = self.votes.write().unwrap();
+        let ratings = self.recompute_ratings(&votes);
+        self.apply_ratings(ratings);
+        drop(votes);
+        self.get_items()
+    }
+
+    /// An in-memory copy of this event's items and judges rated by replaying
+    /// `votes` with `engine`, to see how another engine or vote log would have
+    /// ranked them. Nothing is written back to this event.
+    pub fn replayed_with(&self, engine: Arc<dyn RatingEngine>, votes: Vec<Vote>) -> SchedulerState {
+        let copy = SchedulerState::new(engine, self.options.clone());
+        for mut item in self.get_items() {
+            item.set_rating(copy.engine.initial_rating(), copy.engine.as_ref());
+            copy.items.insert(item.id.clone(), item);
+        }
+        *copy.judges.write().unwrap() = self.get_judges();
+        *copy.votes.write().unwrap() = votes;
+        *copy.rating_period.write().unwrap() = self.get_rating_period();
+        copy.replay_votes();
+        copy
+    }
+
+    fn recompute_ratings(&self, votes: &[Vote]) -> HashMap<String, Glicko2> {
+        let ids: Vec<String> = self.items.iter().map(|i| i.key().clone()).collect();
+        votelog::replay(
+            ids,
+            votes,
+            self.engine.as_ref(),
+            self.options.rating_mode,
+            *self.rating_period.read().unwrap(),
+        )
+    }
+
+    fn apply_ratings(&self, ratings: HashMap<String, Glicko2>) {
+        for (id, rating) in ratings {
+            if let Some(mut item) = self.items.get_mut(&id) {
+                item.set_rating(rating, self.engine.as_ref());
+                self.persist(|s| s.save_item(&item));
+            }
+        }
+    }
+
+    pub fn get_rating_period(&self) -> u64 {
+        *self.rating_period.read().unwrap()
+    }
+
+    /// Closes the open rating period, rating every item against all of the
+    /// verdicts cast during it, and returns the number of the period that closed.
+    pub fn close_rating_period(&self) -> Result<u64, SchedulerError> {
+        if self.options.rating_mode != RatingMode::Period {
+            return Err(SchedulerError::InvalidState(
+                "rating periods are only used in period rating mode".to_string(),
+            ));
+        }
+
+        // block new verdicts while the period is being closed
+        let votes = self.votes.write().unwrap();
+        if self.get_state() == States::End {
+            return Err(SchedulerError::InvalidState(
+                "the event has ended, rankings are frozen".to_string(),
+            ));
+        }
+        Ok(self.close_period_locked(&votes))
+    }
+
+    /// Closes the open period. The caller must hold the vote log write lock.
+    fn close_period_locked(&self, votes: &[Vote]) -> u64 {
+        let mut period = self.rating_period.write().unwrap();
+        let ratings: HashMap<String, Glicko2> = self
+            .items
+            .iter()
+            .map(|i| (i.key().clone(), i.rating))
+            .collect();
+        let batch: Vec<&Vote> = votelog::effective(votes)
+            .into_iter()
+            .filter(|v| v.period == *period)
+            .collect();
+        tracing::info!(
+            "closing rating period {} with {} verdicts",
+            *period,
+            batch.len()
+        );
+        self.apply_ratings(votelog::close_period(ratings, &batch, self.engine.as_ref()));
+
+        let closed = *period;
+        *period += 1;
+        self.persist(|s| s.save_rating_period(*period));
+        closed
     }
 
     pub fn get_state(&self) -> States {
@@ -205,6 +1071,123 @@ impl SchedulerState {
         *state
     }
 
+    fn set_state(&self, state: &mut States, to: States) {
+        tracing::info!("scheduler state {} -> {}", state.as_str(), to.as_str());
+        *state = to;
+        self.persist(|s| s.save_state(to));
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.read().unwrap()
+    }
+
+    pub fn queue_len(&self) -> usize {
+        self.mq.read().unwrap().len()
+    }
+
+    fn set_paused(&self, paused: bool) -> Result<(), SchedulerError> {
+        let state = self.get_state();
+        if !matches!(state, States::Init | States::Continuous) {
+            return Err(SchedulerError::InvalidState(format!(
+                "cannot pause or resume judging in {} state",
+                state.as_str()
+            )));
+        }
+        let mut current = self.paused.write().unwrap();
+        if *current == paused {
+            let msg = if paused {
+                "judging is already paused"
+            } else {
+                "judging is not paused"
+            };
+            return Err(SchedulerError::InvalidState(msg.to_string()));
+        }
+        *current = paused;
+        self.persist(|s| s.save_paused(paused));
+        tracing::info!("judging {}", if paused { "paused" } else { "resumed" });
+        Ok(())
+    }
+
+    /// Stops handing out new matches. Judges can still submit verdicts for the
+    /// matches they already hold.
+    pub fn pause(&self) -> Result<(), SchedulerError> {
+        self.set_paused(true)
+    }
+
+    pub fn resume(&self) -> Result<(), SchedulerError> {
+        self.set_paused(false)
+    }
+
+    /// Moves from `Init` to `Continuous` without waiting for every seed match
+    /// to be visited. Unvisited seed matches stay queued and are still handed out first.
+    pub fn advance_to_continuous(&self) -> Result<States, SchedulerError> {
+        let mut state = self.current_state.write().unwrap();
+        if *state != States::Init {
+            return Err(SchedulerError::InvalidState(format!(
+                "can only advance to Continuous from Init, not {}",
+                state.as_str()
+            )));
+        }
+        self.set_state(&mut state, States::Continuous);
+        Ok(States::Continuous)
+    }
+
+    /// Ends the event: no more matches are handed out and no more verdicts are
+    /// accepted, so the rankings are final. In period mode the open period is
+    /// closed first so that its verdicts count.
+    pub fn end(&self) -> Result<States, SchedulerError> {
+        let votes = self.votes.write().unwrap();
+        let mut state = self.current_state.write().unwrap();
+        if !matches!(*state, States::Init | States::Continuous) {
+            return Err(SchedulerError::InvalidState(format!(
+                "cannot end the event from {} state",
+                state.as_str()
+            )));
+        }
+        if self.options.rating_mode == RatingMode::Period {
+            self.close_period_locked(&votes);
+        }
+        for lease in self.get_leases() {
+            self.leases.remove(&lease.match_id);
+            self.persist(|s| s.delete_lease(&lease.match_id));
+        }
+        self.set_state(&mut state, States::End);
+        Ok(States::End)
+    }
+
+    /// Throws away every match, lease, verdict and judge history and puts all
+    /// items back at the initial rating. Items and judges are kept.
+    pub fn reset(&self) -> Result<States, SchedulerError> {
+        let mut votes = self.votes.write().unwrap();
+        let mut state = self.current_state.write().unwrap();
+        if *state == States::NoState {
+            return Err(SchedulerError::InvalidState(
+                "matchmaking has not started, nothing to reset".to_string(),
+            ));
+        }
+
+        votes.clear();
+        self.leaderboards.lock().unwrap().clear();
+        self.matches.clear();
+        self.mq.write().unwrap().clear();
+        self.leases.clear();
+        self.judge_history.clear();
+        self.skips.write().unwrap().clear();
+        self.unavailable.write().unwrap().clear();
+        *self.rating_period.write().unwrap() = 0;
+        *self.paused.write().unwrap() = false;
+        self.persist(|s| s.reset());
+        let initial: HashMap<String, Glicko2> = self
+            .items
+            .iter()
+            .map(|i| (i.key().clone(), self.engine.initial_rating()))
+            .collect();
+        self.apply_ratings(initial);
+
+        self.set_state(&mut state, States::NoState);
+        Ok(States::NoState)
+    }
+
     pub fn get_judges(&self) -> Vec<Judge> {
         let binding = self.judges.clone();
         let iter = binding.read().unwrap();
@@ -215,38 +1198,41 @@ impl SchedulerState {
         v
     }
 
-    pub fn get_items(&self) -> Vec<Box<Item>> {
+    /// Every item, ordered by id so that nothing built from the list depends
+    /// on how the map happens to be laid out.
+    pub fn get_items(&self) -> Vec<Item> {
         let iter = &self.items;
-        let mut v: Vec<Box<Item>> = vec![];
+        let mut v: Vec<Item> = vec![];
         for i in iter.iter() {
             v.push(i.value().clone());
         }
+        v.sort_by(|a, b| a.id.cmp(&b.id));
         v
     }
 
-    fn state_machine_internal_transition(&self) -> Result<States, Box<dyn Error + '_>> {
+    fn state_machine_internal_transition(&self) -> States {
         let guard = self.current_state.clone();
         let mut state = guard.write().unwrap();
 
         match *state {
-            States::NoState => Ok(States::NoState),
+            States::NoState => States::NoState,
             States::Init => {
-                let q = self.mq.read()?;
+                let q = self.mq.read().unwrap();
                 let peek = q.peek_min();
                 if let Some(p) = peek {
                     if *p.1 < 1 {
                         *state = States::Init;
-                        return Ok(States::Init);
+                        return States::Init;
                     }
-                    *state = States::Continuous;
-                    Ok(States::Continuous)
+                    self.set_state(&mut state, States::Continuous);
+                    States::Continuous
                 } else {
                     *state = States::Init;
-                    Ok(States::Init)
+                    States::Init
                 }
             }
-            States::Continuous => Ok(States::Continuous),
-            States::End => Ok(States::End),
+            States::Continuous => States::Continuous,
+            States::End => States::End,
         }
     }
 
@@ -254,45 +1240,51 @@ impl SchedulerState {
         self.matches.clone()
     }
 
-    pub fn get_match_pairs(&self) -> Result<Vec<Arc<MatchPair>>, Box<dyn Error>> {
+    pub fn get_match_pairs(&self) -> Vec<Arc<MatchPair>> {
         let mut matches: Vec<Arc<MatchPair>> = vec![];
         let dmap = self.get_matches();
         for entry in dmap.iter() {
             let val = entry.clone();
             matches.push(val.clone());
         }
-        Ok(matches)
+        matches
     }
 
-    pub fn seed_start(&self, n: usize) -> bool {
-        if self.get_state() != States::NoState {
-            return false;
+    /// Creates the seed matches and moves to `Init`. Like every other
+    /// transition it locks the state before the queue, and checks that
+    /// matchmaking has not started while holding that lock so that concurrent
+    /// calls cannot both seed.
+    pub fn seed_start(&self, n: usize) -> Result<(), SchedulerError> {
+        let mut state = self.current_state.write().unwrap();
+        if *state != States::NoState {
+            return Err(SchedulerError::InvalidState(format!(
+                "cannot start matchmaking in {} state",
+                state.as_str()
+            )));
         }
 
         let matches = self.get_matches();
+        let mut pq = self.mq.write().unwrap();
 
-        let pq_binding = self.mq.clone();
-        let mut pq = pq_binding.write().unwrap();
-
-        let item_vec = self.get_items();
+        let item_vec: Vec<Item> = self.get_items().into_iter().filter(|i| i.active).collect();
 
-        let mut starter_matches = create_initial_matches(&item_vec, n);
+        let mut starter_matches =
+            create_initial_matches(&item_vec, n, &mut self.rng.lock().unwrap());
         for m in starter_matches.drain(..) {
             pq.push(m.match_pair_id.clone(), m.visit_count);
+            self.persist_match(&m, true);
             matches.insert(m.match_pair_id.clone(), Arc::from(m));
         }
 
-        let state_binding = self.current_state.clone();
-        let mut old_state = state_binding.write().unwrap();
-        *old_state = States::Init;
-
-        true
+        self.set_state(&mut state, States::Init);
+        Ok(())
     }
 
-    pub fn add_items(&self, new_items: Vec<Box<Item>>) {
+    pub fn add_items(&self, new_items: Vec<Item>) {
         let items = &self.items;
         for item in new_items {
             let id: String = item.id.clone();
+            self.persist(|s| s.save_item(&item));
             items.insert(id, item);
         }
     }
@@ -300,75 +1292,351 @@ impl SchedulerState {
     pub fn add_item(&self, item: Item) {
         let items = &self.items;
         let id: String = item.id.clone();
-        items.insert(id, Box::new(item));
+        self.persist(|s| s.save_item(&item));
+        items.insert(id, item);
+    }
+
+    /// Calls `pick` with the name keys of every item, then adds the items it
+    /// picked. No item is added or renamed in between, so `pick` can leave out
+    /// duplicates knowing the names it was given are the only ones taken.
+    pub fn add_unique_items<T>(&self, pick: impl FnOnce(HashSet<String>) -> (T, Vec<Item>)) -> T {
+        let _names = self.item_names.lock().unwrap();
+        let existing = self.items.iter().map(|i| name_key(&i.name)).collect();
+        let (picked, new_items) = pick(existing);
+        self.add_items(new_items);
+        picked
     }
 
+    /// Calls `pick` with the lowercased email of every judge, then adds the
+    /// judges it picked, holding the judges lock throughout like
+    /// `create_judge`.
+    pub fn add_unique_judges<T>(&self, pick: impl FnOnce(HashSet<String>) -> (T, Vec<Judge>)) -> T {
+        let mut judges = self.judges.write().unwrap();
+        let existing = judges.iter().map(|j| j.email.to_lowercase()).collect();
+        let (picked, new_judges) = pick(existing);
+        for judge in &new_judges {
+            self.persist(|s| s.save_judge(judge));
+        }
+        judges.extend(new_judges);
+        picked
+    }
+
+    /// Adds a judge without the checks `create_judge` makes.
+    #[cfg(test)]
     pub fn add_judge(&self, new_judge: Judge) {
         let binding = self.judges.clone();
         let mut judges = binding.write().unwrap();
+        self.persist(|s| s.save_judge(&new_judge));
         judges.push(new_judge);
     }
 
     pub fn add_judges(&self, new_judges: &mut Vec<Judge>) {
         let binding = self.judges.clone();
         let mut judges = binding.write().unwrap();
+        for judge in new_judges.iter() {
+            self.persist(|s| s.save_judge(judge));
+        }
         judges.append(new_judges);
     }
 
-    fn find_next_match(&self) -> Result<Arc<MatchPair>, Box<SchedulerError>> {
+    /// Adds an item, held to the same rules as renaming one.
+    pub fn create_item(
+        &self,
+        name: &str,
+        location: String,
+        description: String,
+    ) -> Result<Item, SchedulerError> {
+        let _names = self.item_names.lock().unwrap();
+        let name = self.check_item_name(name, None)?;
+        let item = Item::new(name, location, description, self.engine.as_ref());
+        self.add_item(item.clone());
+        Ok(item)
+    }
+
+    /// Adds a judge, held to the same rules as changing a judge's email.
+    pub fn create_judge(&self, email: &str) -> Result<Judge, SchedulerError> {
+        let email = check_email(email)?;
+        let mut judges = self.judges.write().unwrap();
+        check_email_free(&judges, &email, None)?;
+        let judge = Judge::new(email);
+        self.persist(|s| s.save_judge(&judge));
+        judges.push(judge.clone());
+        Ok(judge)
+    }
+
+    /// Trims `name` and makes sure no item other than `item_id` goes by it.
+    fn check_item_name(&self, name: &str, item_id: Option<&str>) -> Result<String, SchedulerError> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(SchedulerError::Validation(
+                "name cannot be empty".to_string(),
+            ));
+        }
+        let key = name_key(&name);
+        if self
+            .items
+            .iter()
+            .any(|i| Some(i.id.as_str()) != item_id && name_key(&i.name) == key)
+        {
+            return Err(SchedulerError::Conflict(format!(
+                "an item named {} already exists",
+                name
+            )));
+        }
+        Ok(name)
+    }
+
+    /// Applies the fields set in `update` to an item. Withdrawing an item takes
+    /// its unjudged matches out of the queue and keeps it out of new pairings;
+    /// verdicts already cast on it stay in the log.
+    pub fn update_item(&self, item_id: &str, update: ItemUpdate) -> Result<Item, SchedulerError> {
+        let names = self.item_names.lock().unwrap();
+        let name = match update.name {
+            Some(name) => Some(self.check_item_name(&name, Some(item_id))?),
+            None => None,
+        };
+
+        let (item, was_active) = {
+            let Some(mut item) = self.items.get_mut(item_id) else {
+                return Err(SchedulerError::NotFound(format!(
+                    "item {} does not exist",
+                    item_id
+                )));
+            };
+            let was_active = item.active;
+            if let Some(name) = name {
+                item.name = name;
+            }
+            if let Some(location) = update.location {
+                item.location = location;
+            }
+            if let Some(description) = update.description {
+                item.description = description;
+            }
+            if let Some(active) = update.active {
+                item.active = active;
+            }
+            self.persist(|s| s.save_item(&item));
+            (item.clone(), was_active)
+        };
+        drop(names);
+        if item.active != was_active {
+            self.recirculate(item_id, item.active);
+            tracing::info!(
+                "item {} {}",
+                item_id,
+                if item.active {
+                    "reactivated"
+                } else {
+                    "withdrawn"
+                }
+            );
+        }
+        Ok(item)
+    }
+
+    /// Takes the unjudged matches of a withdrawn item out of the queue, or puts
+    /// them back once both of their items are active again. Leased matches are
+    /// left for the judge holding them to finish.
+    fn recirculate(&self, item_id: &str, active: bool) {
+        let unjudged: Vec<Arc<MatchPair>> = self
+            .matches
+            .iter()
+            .filter(|m| m.winner.is_none() && (m.i1 == item_id || m.i2 == item_id))
+            .map(|m| m.clone())
+            .collect();
+        let mut mq = self.mq.write().unwrap();
+        for m in unjudged {
+            let queued = mq.get(&m.match_pair_id).is_some();
+            if !active && queued {
+                mq.remove(&m.match_pair_id);
+                self.persist_match(&m, false);
+            } else if active
+                && !queued
+                && self.is_active(&m.i1)
+                && self.is_active(&m.i2)
+                && !self.leases.contains_key(&m.match_pair_id)
+            {
+                mq.push(m.match_pair_id.clone(), m.visit_count);
+                self.persist_match(&m, true);
+            }
+        }
+    }
+
+    /// Applies the fields set in `update` to a judge. Deactivating a judge
+    /// hands the matches they hold to someone else; their verdicts still count.
+    pub fn update_judge(
+        &self,
+        judge_id: &str,
+        update: JudgeUpdate,
+    ) -> Result<Judge, SchedulerError> {
+        let email = match update.email {
+            Some(email) => Some(check_email(&email)?),
+            None => None,
+        };
+
+        let judge = {
+            let mut judges = self.judges.write().unwrap();
+            if let Some(email) = &email {
+                check_email_free(&judges, email, Some(judge_id))?;
+            }
+            let Some(judge) = judges.iter_mut().find(|j| j.id == judge_id) else {
+                return Err(SchedulerError::NotFound(format!(
+                    "judge {} does not exist",
+                    judge_id
+                )));
+            };
+            if let Some(email) = email {
+                judge.email = email;
+            }
+            if let Some(active) = update.active {
+                judge.active = active;
+            }
+            self.persist(|s| s.save_judge(judge));
+            judge.clone()
+        };
+        if !judge.active {
+            for lease in self.get_leases() {
+                if lease.judge_id == judge.id {
+                    self.release(&lease);
+                    tracing::info!(
+                        "released match {} held by deactivated judge {}",
+                        lease.match_id,
+                        judge.id
+                    );
+                }
+            }
+        }
+        Ok(judge)
+    }
+
+    /// Issues a new token for a judge, replacing any they were issued before.
+    /// Only its hash is kept, so the token has to be handed to the judge now.
+    pub fn issue_token(&self, judge_id: &str) -> String {
+        self.revoke_tokens(judge_id);
+        let token = auth::new_token();
+        let token_hash = auth::hash_token(&token);
+        self.persist(|s| s.save_judge_token(&token_hash, judge_id));
+        self.judge_tokens.insert(token_hash, judge_id.to_string());
+        token
+    }
+
+    /// Stops every token issued to a judge from working.
+    pub fn revoke_tokens(&self, judge_id: &str) {
+        self.judge_tokens.retain(|_, id| id != judge_id);
+        self.persist(|s| s.delete_judge_tokens(judge_id));
+    }
+
+    pub fn judge_for_token(&self, token: &str) -> Option<Judge> {
+        let judge_id = self.judge_tokens.get(&auth::hash_token(token))?.clone();
+        self.get_judge(&judge_id)
+    }
+
+    pub fn get_judge(&self, judge_id: &str) -> Option<Judge> {
+        self.judges
+            .read()
+            .unwrap()
+            .iter()
+            .find(|judge| judge.id == judge_id)
+            .cloned()
+    }
+
+    pub fn get_judge_history(&self, judge_id: &str) -> JudgeHistory {
+        self.judge_history
+            .get(judge_id)
+            .map(|h| h.clone())
+            .unwrap_or_default()
+    }
+
+    fn find_next_match(&self, judge: &Judge) -> Result<Arc<MatchPair>, SchedulerError> {
         let state = self.get_state();
+        let history = self.get_judge_history(&judge.id);
         match state {
-            States::NoState => Err(Box::new(SchedulerError::new(
-                "Cannot get next match while in NONE state",
-            ))),
-            States::Init => self.get_from_queue(0).unwrap(),
-            States::Continuous => self.get_continuous_stage(),
-            States::End => Err(Box::new(SchedulerError::new(
-                "Cannot get next match while in END state",
-            ))),
+            States::NoState => Err(SchedulerError::InvalidState(
+                "cannot get next match while in NoState state".to_string(),
+            )),
+            States::Init => match self.get_from_queue(0, &history) {
+                Some(m) => Ok(m),
+                // this judge has already seen every unvisited seed match
+                None => self.get_continuous_stage(&history),
+            },
+            States::Continuous => self.get_continuous_stage(&history),
+            States::End => Err(SchedulerError::InvalidState(
+                "cannot get next match while in End state".to_string(),
+            )),
         }
     }
 
-    fn get_from_queue(&self, min_prio: i32) -> Option<Result<Arc<MatchPair>, Box<SchedulerError>>> {
-        let q = self.mq.write().unwrap();
+    /// Returns the least visited unjudged queued match with a priority of at most
+    /// `max_prio` that the judge is still allowed to see.
+    fn get_from_queue(&self, max_prio: i32, history: &JudgeHistory) -> Option<Arc<MatchPair>> {
+        let q = self.mq.read().unwrap();
         let matches = self.get_matches();
-        if let Some(best) = q.peek_min() {
-            let key = best.0;
-            let prio = *best.1;
-            if min_prio < prio {
-                return None;
-            }
-            if let Some(val) = matches.get(key) {
-                Some(Ok(val.clone()))
+        let mut candidates: Vec<(&String, &i32)> = q
+            .iter()
+            .filter(|(key, prio)| **prio <= max_prio && !self.leases.contains_key(*key))
+            .collect();
+        candidates.sort_by_key(|(_, prio)| **prio);
+        candidates.into_iter().find_map(|(key, _)| {
+            let m = matches.get(key)?;
+            // judged matches stay queued but cannot take another verdict
+            if m.winner.is_none()
+                && history.allows(&m.i1, &m.i2, self.options.max_item_views)
+                && self.is_available(&m.i1)
+                && self.is_available(&m.i2)
+            {
+                Some(m.clone())
             } else {
-                Some(Err(Box::new(SchedulerError::new("Match key not found"))))
+                None
             }
-        } else {
-            Some(Err(Box::new(SchedulerError::new("Could not peek queue"))))
-        }
+        })
     }
 
-    fn get_continuous_stage(&self) -> Result<Arc<MatchPair>, Box<SchedulerError>> {
-        if let Some(Ok(queue_item)) = self.get_from_queue(1) {
+    fn get_continuous_stage(
+        &self,
+        history: &JudgeHistory,
+    ) -> Result<Arc<MatchPair>, SchedulerError> {
+        if let Some(queue_item) = self.get_from_queue(1, history) {
             return Ok(queue_item);
         }
 
-        let rng = &mut rand::thread_rng();
-        let items = self.get_items();
+        let max_views = self.options.max_item_views;
+        let items: Vec<Item> = self
+            .get_items()
+            .into_iter()
+            .filter(|i| history.can_see_item(&i.id, max_views) && self.is_available(&i.id))
+            .collect();
+        let mut pair_counts: HashMap<(String, String), u32> = HashMap::new();
+        for m in self.matches.iter() {
+            *pair_counts
+                .entry(pairing::pair_key(&m.i1, &m.i2))
+                .or_insert(0) += 1;
+        }
 
-        let choices: Vec<Box<Item>> = items.choose_multiple(rng, 2).cloned().collect();
-        // TODO: keep a sorted array of closest scores and then select best match
+        let (i1, i2) = self
+            .selector
+            .select(
+                &items,
+                self.engine.as_ref(),
+                &pair_counts,
+                &history.pairs,
+                &mut self.rng.lock().unwrap(),
+            )
+            .ok_or_else(|| {
+                SchedulerError::NotFound("no more matches available for this judge".to_string())
+            })?;
 
         let id = uuid::Uuid::new_v4().to_string();
         let m = MatchPair {
             match_pair_id: id.clone(),
-            i1: choices[0].id.clone(),
-            i2: choices[1].id.clone(),
+            i1,
+            i2,
             visit_count: 0,
             winner: None,
             judge_id: None,
         };
 
+        self.persist_match(&m, false);
         let as_arc = Arc::from(m);
 
         let hm = self.get_matches();
@@ -376,30 +1644,714 @@ impl SchedulerState {
         Ok(as_arc)
     }
 
-    pub fn give_judge_next_match(
-        &self,
-        judge: &Judge,
-    ) -> Result<Arc<MatchPair>, Box<dyn Error + '_>> {
-        self.state_machine_internal_transition()?;
-        let nm = self.find_next_match();
-        match nm {
-            Ok(m) => {
-                let m_id = &m.match_pair_id;
-                let mut q = self.mq.write().unwrap();
-                q.change_priority_by(m_id, |i| *i += 1);
-                let new = Arc::new(MatchPair {
-                    match_pair_id: m_id.clone(),
-                    i1: m.i1.clone(),
-                    i2: m.i2.clone(),
-                    visit_count: m.visit_count + 1,
-                    winner: m.winner,
-                    judge_id: Some(judge.id.clone()),
-                });
-                self.get_matches()
-                    .insert(m.match_pair_id.clone(), new.clone());
-                Ok(new)
+    pub fn give_judge_next_match(&self, judge: &Judge) -> Result<Arc<MatchPair>, SchedulerError> {
+        if self.is_paused() {
+            return Err(SchedulerError::InvalidState(
+                "judging is paused".to_string(),
+            ));
+        }
+        match self.get_judge(&judge.id) {
+            None => {
+                return Err(SchedulerError::NotFound(format!(
+                    "judge {} does not exist",
+                    judge.id
+                )))
+            }
+            Some(known) if !known.active => {
+                return Err(SchedulerError::Forbidden(
+                    "judge has been deactivated".to_string(),
+                ))
+            }
+            Some(_) => {}
+        }
+        self.release_expired_leases();
+        self.state_machine_internal_transition();
+        loop {
+            let m = self.find_next_match(judge)?;
+            let m_id = &m.match_pair_id;
+            let lease = Lease {
+                match_id: m_id.clone(),
+                judge_id: judge.id.clone(),
+                expires_at_ms: votelog::now_ms() + self.options.lease_timeout.as_millis() as u64,
+            };
+            if !self.claim_lease(&lease) {
+                // another judge got to this match first, it is skipped when selecting again
+                continue;
+            }
+            self.persist(|s| s.save_lease(&lease));
+
+            self.judge_history
+                .entry(judge.id.clone())
+                .or_default()
+                .record(&m.i1, &m.i2);
+            self.persist(|s| s.append_judge_history(&judge.id, &m.i1, &m.i2));
+
+            let mut q = self.mq.write().unwrap();
+            q.change_priority_by(m_id, |i| *i += 1);
+            let new = Arc::new(MatchPair {
+                match_pair_id: m_id.clone(),
+                i1: m.i1.clone(),
+                i2: m.i2.clone(),
+                visit_count: m.visit_count + 1,
+                winner: m.winner,
+                judge_id: Some(judge.id.clone()),
+            });
+            self.persist_match(&new, q.get(m_id).is_some());
+            self.get_matches()
+                .insert(m.match_pair_id.clone(), new.clone());
+            return Ok(new);
+        }
+    }
+
+    /// Takes the lease on a match unless someone else already holds it or it
+    /// was judged after being selected.
+    fn claim_lease(&self, lease: &Lease) -> bool {
+        match self.leases.entry(lease.match_id.clone()) {
+            Entry::Occupied(_) => return false,
+            Entry::Vacant(vacant) => {
+                vacant.insert(lease.clone());
+            }
+        }
+        // a verdict in flight holds the log lock until the match is marked judged
+        let judged = self
+            .votes
+            .read()
+            .unwrap()
+            .iter()
+            .any(|v| v.match_id == lease.match_id);
+        if judged {
+            self.leases.remove(&lease.match_id);
+        }
+        !judged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::rating::{engine_for, EngineKind};
+
+    fn state_with_items(n: usize, options: SchedulerOptions) -> SchedulerState {
+        let engine = engine_for(EngineKind::Glicko2);
+        let state = SchedulerState::new(engine.clone(), options);
+        for i in 0..n {
+            state.add_item(Item::new(
+                format!("item {}", i),
+                String::new(),
+                String::new(),
+                engine.as_ref(),
+            ));
+        }
+        state
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_judge_never_sees_same_pair_twice() {
+        let state = state_with_items(
+            3,
+            SchedulerOptions {
+                max_item_views: None,
+                ..SchedulerOptions::default()
+            },
+        );
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(2).unwrap();
+
+        // judges the event does not know cannot take up matches
+        let stranger = Judge::new("stranger@example.com".to_string());
+        assert!(matches!(
+            state.give_judge_next_match(&stranger),
+            Err(SchedulerError::NotFound(_))
+        ));
+        assert!(state.get_leases().is_empty());
+
+        let mut seen = HashSet::new();
+        for _ in 0..3 {
+            let m = state.give_judge_next_match(&judge).unwrap();
+            assert!(seen.insert(pairing::pair_key(&m.i1, &m.i2)));
+        }
+        // all three possible pairs have been shown
+        assert!(state.give_judge_next_match(&judge).is_err());
+
+        // a different judge is unaffected
+        assert!(state.give_judge_next_match(&other).is_ok());
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_item_view_cap() {
+        let state = state_with_items(
+            4,
+            SchedulerOptions {
+                max_item_views: Some(1),
+                ..SchedulerOptions::default()
+            },
+        );
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.seed_start(1).unwrap();
+
+        let first = state.give_judge_next_match(&judge).unwrap();
+        let second = state.give_judge_next_match(&judge).unwrap();
+        let shown: HashSet<&String> = [&first.i1, &first.i2, &second.i1, &second.i2]
+            .into_iter()
+            .collect();
+        assert_eq!(shown.len(), 4);
+        assert!(state.give_judge_next_match(&judge).is_err());
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_verdict_requires_lease() {
+        let state = state_with_items(2, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(1).unwrap();
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        assert!(matches!(
+            state.judge_match(&other, &m.match_pair_id, MatchWinner::A, false),
+            Err(SchedulerError::Forbidden(_))
+        ));
+        assert!(state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .is_ok());
+        assert!(state.get_leases().is_empty());
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_verdict_validation() {
+        let state = state_with_items(2, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        let stranger = Judge::new("stranger@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(1).unwrap();
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        let id = &m.match_pair_id;
+        assert!(matches!(
+            state.judge_match(&judge, "nope", MatchWinner::A, false),
+            Err(SchedulerError::NotFound(_))
+        ));
+        assert!(matches!(
+            state.judge_match(&stranger, id, MatchWinner::A, false),
+            Err(SchedulerError::Unauthorized(_))
+        ));
+        let impostor = Judge::from_id("wrong@example.com".to_string(), judge.id.clone());
+        assert!(matches!(
+            state.judge_match(&impostor, id, MatchWinner::A, false),
+            Err(SchedulerError::Unauthorized(_))
+        ));
+
+        assert!(state.judge_match(&judge, id, MatchWinner::A, false).is_ok());
+        let after_first = state.get_items();
+        assert!(matches!(
+            state.judge_match(&judge, id, MatchWinner::A, false),
+            Err(SchedulerError::Conflict(_))
+        ));
+        assert_eq!(state.get_votes().len(), 1);
+        assert_eq!(state.get_items(), after_first);
+
+        assert!(matches!(
+            state.judge_match(&other, id, MatchWinner::B, true),
+            Err(SchedulerError::Forbidden(_))
+        ));
+        assert!(state.judge_match(&judge, id, MatchWinner::B, true).is_ok());
+        assert_eq!(state.get_votes().len(), 2);
+        assert_eq!(state.get_votes()[1].amends, Some(0));
+
+        // the amendment replaces the original verdict rather than adding to it
+        let b = state
+            .get_items()
+            .into_iter()
+            .find(|i| i.id == m.i2)
+            .unwrap();
+        let (_, expected) = state.engine().rate(
+            &state.engine().initial_rating(),
+            &state.engine().initial_rating(),
+            MatchWinner::B,
+        );
+        assert_eq!(b.rating, expected);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_concurrent_judges_get_distinct_leases() {
+        for _ in 0..50 {
+            let state = state_with_items(
+                2,
+                SchedulerOptions {
+                    max_item_views: None,
+                    ..SchedulerOptions::default()
+                },
+            );
+            let judges = [
+                Judge::new("judge@example.com".to_string()),
+                Judge::new("other@example.com".to_string()),
+            ];
+            for judge in &judges {
+                state.add_judge(judge.clone());
+            }
+            // a single seed match for both judges to race for
+            state.seed_start(1).unwrap();
+
+            let barrier = std::sync::Barrier::new(judges.len());
+            let given: Vec<Arc<MatchPair>> = std::thread::scope(|scope| {
+                let handles: Vec<_> = judges
+                    .iter()
+                    .map(|judge| {
+                        let (state, barrier) = (&state, &barrier);
+                        scope.spawn(move || {
+                            barrier.wait();
+                            state.give_judge_next_match(judge).unwrap()
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+
+            assert_ne!(given[0].match_pair_id, given[1].match_pair_id);
+            assert_eq!(state.get_leases().len(), 2);
+            for (judge, m) in judges.iter().zip(&given) {
+                state
+                    .judge_match(judge, &m.match_pair_id, MatchWinner::A, false)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_expired_lease_is_released() {
+        let state = state_with_items(
+            2,
+            SchedulerOptions {
+                lease_timeout: Duration::ZERO,
+                ..SchedulerOptions::default()
+            },
+        );
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(1).unwrap();
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        assert_eq!(state.release_expired_leases(), 1);
+        assert!(matches!(
+            state.judge_match(&judge, &m.match_pair_id, MatchWinner::A, false),
+            Err(SchedulerError::Forbidden(_))
+        ));
+
+        let released = state.get_matches().get(&m.match_pair_id).unwrap().clone();
+        assert_eq!(released.visit_count, 0);
+        assert_eq!(released.judge_id, None);
+
+        // still unvisited, so the seed match goes to the next judge
+        let again = state.give_judge_next_match(&other).unwrap();
+        assert_eq!(again.match_pair_id, m.match_pair_id);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_admin_transitions() {
+        let state = state_with_items(4, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+
+        assert!(matches!(
+            state.pause(),
+            Err(SchedulerError::InvalidState(_))
+        ));
+        assert!(matches!(state.end(), Err(SchedulerError::InvalidState(_))));
+        assert!(matches!(
+            state.reset(),
+            Err(SchedulerError::InvalidState(_))
+        ));
+        state.seed_start(1).unwrap();
+
+        state.pause().unwrap();
+        assert!(matches!(
+            state.pause(),
+            Err(SchedulerError::InvalidState(_))
+        ));
+        assert!(matches!(
+            state.give_judge_next_match(&judge),
+            Err(SchedulerError::InvalidState(_))
+        ));
+        state.resume().unwrap();
+        let m = state.give_judge_next_match(&judge).unwrap();
+
+        assert_eq!(state.advance_to_continuous(), Ok(States::Continuous));
+        assert!(matches!(
+            state.advance_to_continuous(),
+            Err(SchedulerError::InvalidState(_))
+        ));
+
+        state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .unwrap();
+        let other = state.give_judge_next_match(&judge).unwrap();
+        assert_eq!(state.end(), Ok(States::End));
+        let frozen = state.get_items();
+        assert!(state.get_leases().is_empty());
+        assert!(matches!(
+            state.judge_match(&judge, &other.match_pair_id, MatchWinner::A, false),
+            Err(SchedulerError::InvalidState(_))
+        ));
+        assert!(matches!(
+            state.judge_match(&judge, &m.match_pair_id, MatchWinner::B, true),
+            Err(SchedulerError::InvalidState(_))
+        ));
+        assert_eq!(state.get_items(), frozen);
+
+        assert_eq!(state.reset(), Ok(States::NoState));
+        assert!(state.get_votes().is_empty());
+        assert!(state.get_match_pairs().is_empty());
+        assert_eq!(state.queue_len(), 0);
+        assert_eq!(state.get_judge_history(&judge.id).views(&m.i1), 0);
+        for item in state.get_items() {
+            assert_eq!(item.rating, state.engine().initial_rating());
+        }
+        state.seed_start(1).unwrap();
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_concurrent_start_seeds_once() {
+        let state = state_with_items(4, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+
+        let started = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| state.seed_start(1)))
+                .collect();
+            // hand out matches while seeding to exercise the lock order
+            scope.spawn(|| {
+                let _ = state.give_judge_next_match(&judge);
+            });
+            handles
+                .into_iter()
+                .filter_map(|h| h.join().unwrap().ok())
+                .count()
+        });
+        assert_eq!(started, 1);
+        assert_eq!(state.get_match_pairs().len(), 2);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_end_closes_open_period() {
+        let state = state_with_items(
+            2,
+            SchedulerOptions {
+                rating_mode: RatingMode::Period,
+                ..SchedulerOptions::default()
+            },
+        );
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.seed_start(1).unwrap();
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .unwrap();
+        state.end().unwrap();
+        assert_eq!(state.get_rating_period(), 1);
+        let winner = state
+            .get_items()
+            .into_iter()
+            .find(|i| i.id == m.i1)
+            .unwrap();
+        assert!(winner.score > 1500.0);
+        assert!(matches!(
+            state.close_rating_period(),
+            Err(SchedulerError::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_skip_returns_match_to_queue() {
+        let state = state_with_items(4, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(1).unwrap();
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        let before = state.get_items();
+        let elsewhere = Some(ItemUnavailable {
+            item_id: "nope".to_string(),
+            scope: UnavailableScope::Judge,
+        });
+        assert!(matches!(
+            state.skip_match(&judge, &m.match_pair_id, SkipReason::Other, elsewhere),
+            Err(SchedulerError::Validation(_))
+        ));
+        assert!(matches!(
+            state.skip_match(&other, &m.match_pair_id, SkipReason::Other, None),
+            Err(SchedulerError::Forbidden(_))
+        ));
+
+        let conflict = Some(ItemUnavailable {
+            item_id: m.i1.clone(),
+            scope: UnavailableScope::Judge,
+        });
+        state
+            .skip_match(
+                &judge,
+                &m.match_pair_id,
+                SkipReason::ConflictOfInterest,
+                conflict,
+            )
+            .unwrap();
+        assert_eq!(state.get_items(), before);
+        assert!(state.get_votes().is_empty());
+        assert!(state.get_leases().is_empty());
+        assert_eq!(state.get_skips().len(), 1);
+        assert!(state.is_queued(&m.match_pair_id));
+
+        // the skipped item is never shown to this judge again
+        while let Ok(next) = state.give_judge_next_match(&judge) {
+            assert!(next.i1 != m.i1 && next.i2 != m.i1);
+        }
+        // but the match goes to the next judge as if it was never handed out
+        let again = state.give_judge_next_match(&other).unwrap();
+        assert_eq!(again.match_pair_id, m.match_pair_id);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_skip_global_unavailability() {
+        let state = state_with_items(4, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(1).unwrap();
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        let absent = Some(ItemUnavailable {
+            item_id: m.i2.clone(),
+            scope: UnavailableScope::Global,
+        });
+        state
+            .skip_match(&judge, &m.match_pair_id, SkipReason::ItemAbsent, absent)
+            .unwrap();
+
+        for j in [&judge, &other] {
+            while let Ok(next) = state.give_judge_next_match(j) {
+                assert!(next.i1 != m.i2 && next.i2 != m.i2);
             }
-            Err(e) => Err(e),
         }
     }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_withdraw_item() {
+        let state = state_with_items(
+            4,
+            SchedulerOptions {
+                max_item_views: None,
+                ..SchedulerOptions::default()
+            },
+        );
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.seed_start(1).unwrap();
+        let m = state.get_match_pairs()[0].clone();
+        let withdrawn = m.i1.clone();
+
+        let update = ItemUpdate {
+            active: Some(false),
+            ..ItemUpdate::default()
+        };
+        assert!(!state.update_item(&withdrawn, update).unwrap().active);
+        assert!(!state.is_queued(&m.match_pair_id));
+        while let Ok(next) = state.give_judge_next_match(&judge) {
+            assert!(next.i1 != withdrawn && next.i2 != withdrawn);
+        }
+        let board = state.leaderboard(RankingMethod::Live, false);
+        assert_eq!(board.len(), 3);
+
+        let update = ItemUpdate {
+            active: Some(true),
+            ..ItemUpdate::default()
+        };
+        state.update_item(&withdrawn, update).unwrap();
+        assert!(state.is_queued(&m.match_pair_id));
+
+        let taken = state.get_items().into_iter().find(|i| i.id != withdrawn);
+        let rename = ItemUpdate {
+            name: Some(format!("  {}  ", taken.unwrap().name.to_uppercase())),
+            ..ItemUpdate::default()
+        };
+        assert!(matches!(
+            state.update_item(&withdrawn, rename),
+            Err(SchedulerError::Conflict(_))
+        ));
+        assert!(matches!(
+            state.update_item("missing", ItemUpdate::default()),
+            Err(SchedulerError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_leaderboard_excluding_withdrawn_items() {
+        let state = state_with_items(3, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.seed_start(1).unwrap();
+        let m = state.give_judge_next_match(&judge).unwrap();
+        state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .unwrap();
+        let update = ItemUpdate {
+            active: Some(false),
+            ..ItemUpdate::default()
+        };
+        state.update_item(&m.i2, update).unwrap();
+
+        let winner = |board: Vec<LeaderboardEntry>| {
+            assert_eq!(board.len(), 2);
+            board.into_iter().find(|e| e.id == m.i1).unwrap()
+        };
+        let kept = winner(state.leaderboard(RankingMethod::Live, false));
+        assert_eq!(kept.wins, 1);
+        assert!(kept.rating > 1500.0);
+        let excluded = winner(state.leaderboard(RankingMethod::Live, true));
+        assert_eq!(excluded.wins, 0);
+        assert_eq!(excluded.rating, 1500.0);
+        // the verdict itself stays in the log
+        assert_eq!(state.get_votes().len(), 1);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_cached_leaderboard_follows_changes() {
+        let state = state_with_items(2, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.seed_start(1).unwrap();
+
+        let before = state.leaderboard(RankingMethod::BradleyTerry, false);
+        assert_eq!(
+            state.leaderboard(RankingMethod::BradleyTerry, false),
+            before
+        );
+        assert!(before.iter().all(|e| e.comparisons == 0));
+
+        let m = state.give_judge_next_match(&judge).unwrap();
+        state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .unwrap();
+        let after = state.leaderboard(RankingMethod::BradleyTerry, false);
+        assert_eq!(after[0].id, m.i1);
+        assert_eq!(after[0].wins, 1);
+
+        let rename = ItemUpdate {
+            name: Some("renamed".to_string()),
+            ..ItemUpdate::default()
+        };
+        state.update_item(&m.i1, rename).unwrap();
+        assert_eq!(
+            state.leaderboard(RankingMethod::BradleyTerry, false)[0].name,
+            "renamed"
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_deactivate_judge() {
+        let state = state_with_items(2, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        let other = Judge::new("other@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.add_judge(other.clone());
+        state.seed_start(1).unwrap();
+        let m = state.give_judge_next_match(&judge).unwrap();
+
+        let update = JudgeUpdate {
+            active: Some(false),
+            ..JudgeUpdate::default()
+        };
+        assert!(!state.update_judge(&judge.id, update).unwrap().active);
+        assert!(state.get_leases().is_empty());
+        assert!(state.is_queued(&m.match_pair_id));
+        assert!(matches!(
+            state.give_judge_next_match(&judge),
+            Err(SchedulerError::Forbidden(_))
+        ));
+        assert!(matches!(
+            state.judge_match(&judge, &m.match_pair_id, MatchWinner::A, false),
+            Err(SchedulerError::Forbidden(_))
+        ));
+
+        for (email, valid) in [("OTHER@example.com", true), ("nope", false)] {
+            let update = JudgeUpdate {
+                email: Some(email.to_string()),
+                ..JudgeUpdate::default()
+            };
+            let result = state.update_judge(&judge.id, update);
+            assert!(match valid {
+                true => matches!(result, Err(SchedulerError::Conflict(_))),
+                false => matches!(result, Err(SchedulerError::Validation(_))),
+            });
+        }
+        let update = JudgeUpdate {
+            email: Some("new@example.com".to_string()),
+            ..JudgeUpdate::default()
+        };
+        assert_eq!(
+            state.update_judge(&judge.id, update).unwrap().email,
+            "new@example.com"
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_replayed_with_another_engine() {
+        let state = state_with_items(2, SchedulerOptions::default());
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        state.seed_start(1).unwrap();
+        let m = state.give_judge_next_match(&judge).unwrap();
+        state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .unwrap();
+
+        let elo = state.replayed_with(engine_for(EngineKind::Elo), state.get_votes());
+        let rating = |state: &SchedulerState, id: &str| {
+            state
+                .get_items()
+                .into_iter()
+                .find(|i| i.id == id)
+                .unwrap()
+                .score
+        };
+        assert_eq!(rating(&elo, &m.i1), 1015.0);
+        assert_eq!(rating(&elo, &m.i2), 985.0);
+        assert_eq!(elo.get_judges(), state.get_judges());
+        // the original keeps its own ratings
+        assert!(rating(&state, &m.i1) > 1500.0);
+
+        let none = state.replayed_with(engine_for(EngineKind::Elo), vec![]);
+        assert_eq!(rating(&none, &m.i1), 1000.0);
+    }
 }
@@ -0,0 +1,269 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::{collections::HashMap, f64::consts::PI};
+
+use crate::{
+    error::SchedulerError,
+    event::EventConfig,
+    leaderboard::RankingMethod,
+    rating::{EngineKind, RatingMode},
+    scheduler::{Item, Judge, MatchWinner, SchedulerOptions, SchedulerState},
+};
+
+/// The shape of a synthetic event. Every item has a hidden strength drawn
+/// from a standard normal distribution, and judges prefer the stronger item
+/// of a pair with a probability that grows with the gap between them.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub items: usize,
+    pub judges: usize,
+    /// Verdicts to cast. Fewer are cast if every judge runs out of matches
+    /// they are allowed to see.
+    pub verdicts: usize,
+    /// How erratic judges are: an item `noise` stronger than another is
+    /// preferred about 73% of the time.
+    pub noise: f64,
+    /// Fixes the strengths, the pairing and the verdicts, so the same seed
+    /// always gives the same report.
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SimulatedItem {
+    pub rank: usize,
+    pub name: String,
+    /// The hidden strength the verdicts were drawn from.
+    pub strength: f64,
+    pub rating: f64,
+    pub comparisons: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SimulationReport {
+    pub engine: EngineKind,
+    pub method: RankingMethod,
+    pub verdicts: usize,
+    /// Kendall's tau between the hidden strengths and the final ranking, 1 when
+    /// the order is exactly right and 0 when it is no better than chance.
+    pub kendall_tau: f64,
+    /// How many of the three strongest items finished in the top three.
+    pub top3: usize,
+    pub leaderboard: Vec<SimulatedItem>,
+}
+
+/// A draw from the standard normal distribution, by the Box–Muller transform.
+fn normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+/// Runs a whole event configured by `config` in memory: seeds matchmaking,
+/// lets the judges take turns asking for a match and judging it, closes
+/// rating periods as it goes in period mode, then ranks the items with
+/// `method`.
+pub fn run(
+    config: &EventConfig,
+    simulation: &Simulation,
+    method: RankingMethod,
+) -> Result<SimulationReport, SchedulerError> {
+    if simulation.items < 2 || simulation.judges == 0 {
+        return Err(SchedulerError::Validation(
+            "a simulation needs at least 2 items and 1 judge".to_string(),
+        ));
+    }
+    if !(simulation.noise > 0.0 && simulation.noise.is_finite()) {
+        return Err(SchedulerError::Validation(
+            "noise must be a positive number".to_string(),
+        ));
+    }
+
+    let mut rng = StdRng::seed_from_u64(simulation.seed);
+    let options = SchedulerOptions {
+        seed: Some(rng.gen()),
+        ..config.options()
+    };
+    let state = SchedulerState::new(config.engine(), options);
+    let engine = state.engine();
+    let mut strengths: HashMap<String, f64> = HashMap::new();
+    // ids decide the order items are considered in, so random ones would
+    // undo the seed
+    let items: Vec<Item> = (1..=simulation.items)
+        .map(|i| {
+            let mut item = Item::new(
+                format!("item {}", i),
+                String::new(),
+                String::new(),
+                engine.as_ref(),
+            );
+            item.id = format!("item-{:04}", i);
+            strengths.insert(item.id.clone(), normal(&mut rng));
+            item
+        })
+        .collect();
+    state.add_items(items);
+    let judges: Vec<Judge> = (1..=simulation.judges)
+        .map(|i| Judge::from_id(format!("judge{}@example.com", i), format!("judge-{}", i)))
+        .collect();
+    state.add_judges(&mut judges.clone());
+    state.seed_start(config.seed_rounds)?;
+
+    let period_mode = config.rating_mode == RatingMode::Period;
+    let mut verdicts = 0;
+    // judges in a row that had nothing left to see
+    let mut idle = 0;
+    for judge in judges.iter().cycle() {
+        if verdicts == simulation.verdicts || idle == judges.len() {
+            break;
+        }
+        let Ok(m) = state.give_judge_next_match(judge) else {
+            idle += 1;
+            continue;
+        };
+        idle = 0;
+        let gap = strengths[&m.i1] - strengths[&m.i2];
+        let p = 1.0 / (1.0 + (-gap / simulation.noise).exp());
+        let winner = if rng.gen::<f64>() < p {
+            MatchWinner::A
+        } else {
+            MatchWinner::B
+        };
+        state.judge_match(judge, &m.match_pair_id, winner, false)?;
+        verdicts += 1;
+        // one period per round of judges
+        if period_mode && verdicts % judges.len() == 0 {
+            state.close_rating_period()?;
+        }
+    }
+    if period_mode && verdicts % judges.len() != 0 {
+        state.close_rating_period()?;
+    }
+
+    let leaderboard: Vec<SimulatedItem> = state
+        .leaderboard(method, false)
+        .into_iter()
+        .map(|entry| SimulatedItem {
+            rank: entry.rank,
+            name: entry.name,
+            strength: strengths[&entry.id],
+            rating: entry.rating,
+            comparisons: entry.comparisons,
+        })
+        .collect();
+
+    let (mut concordant, mut discordant) = (0f64, 0f64);
+    for (i, a) in leaderboard.iter().enumerate() {
+        for b in &leaderboard[i + 1..] {
+            if a.strength > b.strength {
+                concordant += 1.0;
+            } else {
+                discordant += 1.0;
+            }
+        }
+    }
+    let mut strongest: Vec<f64> = strengths.values().copied().collect();
+    strongest.sort_by(|a, b| b.total_cmp(a));
+    let cutoff = strongest[strongest.len().min(3) - 1];
+
+    Ok(SimulationReport {
+        engine: config.engine,
+        method,
+        verdicts,
+        kendall_tau: (concordant - discordant) / (concordant + discordant),
+        top3: leaderboard
+            .iter()
+            .take(3)
+            .filter(|item| item.strength >= cutoff)
+            .count(),
+        leaderboard,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+
+    fn simulation(verdicts: usize) -> Simulation {
+        Simulation {
+            items: 10,
+            judges: 8,
+            verdicts,
+            noise: 0.2,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_simulation_recovers_order() {
+        for (engine, rating_mode) in [
+            (EngineKind::Glicko2, RatingMode::Immediate),
+            (EngineKind::Elo, RatingMode::Immediate),
+            (EngineKind::Glicko2, RatingMode::Period),
+        ] {
+            let config = EventConfig {
+                engine,
+                rating_mode,
+                ..EventConfig::default()
+            };
+            let report = run(&config, &simulation(100), RankingMethod::Live).unwrap();
+            assert_eq!(report.verdicts, 100);
+            assert_eq!(report.leaderboard.len(), 10);
+            assert!(
+                report.kendall_tau > 0.5,
+                "{:?} {:?}: {}",
+                engine,
+                rating_mode,
+                report.kendall_tau
+            );
+        }
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_same_seed_same_report() {
+        let config = EventConfig::default();
+        let report = run(&config, &simulation(60), RankingMethod::Live).unwrap();
+        assert_eq!(
+            run(&config, &simulation(60), RankingMethod::Live).unwrap(),
+            report
+        );
+        let reseeded = Simulation {
+            seed: 8,
+            ..simulation(60)
+        };
+        assert_ne!(
+            run(&config, &reseeded, RankingMethod::Live).unwrap(),
+            report
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_simulation_limits() {
+        let config = EventConfig::default();
+        // each judge sees an item at most 3 times, so 8 judges run out
+        // long before a million verdicts
+        let report = run(&config, &simulation(1_000_000), RankingMethod::BradleyTerry).unwrap();
+        assert!(report.verdicts < 8 * 10 * 3);
+        assert!(report.top3 <= 3);
+
+        for bad in [
+            Simulation {
+                items: 1,
+                ..simulation(1)
+            },
+            Simulation {
+                noise: 0.0,
+                ..simulation(1)
+            },
+        ] {
+            assert!(matches!(
+                run(&config, &bad, RankingMethod::Live),
+                Err(SchedulerError::Validation(_))
+            ));
+        }
+    }
+}
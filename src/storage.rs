@@ -0,0 +1,795 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{path::Path, sync::Mutex};
+
+use crate::{
+    event::EventConfig,
+    glicko2::algo::Glicko2,
+    scheduler::{
+        Item, ItemUnavailable, Judge, Lease, MatchPair, MatchWinner, Skip, SkipReason, States,
+        UnavailableScope,
+    },
+    votelog::Vote,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS judges (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    active INTEGER NOT NULL DEFAULT 1
+);
+CREATE TABLE IF NOT EXISTS judge_tokens (
+    token_hash TEXT PRIMARY KEY,
+    judge_id TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS items (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    location TEXT NOT NULL,
+    description TEXT NOT NULL,
+    mu REAL NOT NULL,
+    phi REAL NOT NULL,
+    sigma REAL NOT NULL,
+    active INTEGER NOT NULL DEFAULT 1
+);
+CREATE TABLE IF NOT EXISTS matches (
+    id TEXT PRIMARY KEY,
+    i1 TEXT NOT NULL,
+    i2 TEXT NOT NULL,
+    visit_count INTEGER NOT NULL,
+    winner TEXT,
+    judge_id TEXT,
+    queued INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS votes (
+    seq INTEGER PRIMARY KEY,
+    judge_id TEXT NOT NULL,
+    match_id TEXT NOT NULL,
+    i1 TEXT NOT NULL,
+    i2 TEXT NOT NULL,
+    winner TEXT NOT NULL,
+    timestamp_ms INTEGER NOT NULL,
+    period INTEGER NOT NULL,
+    pre_i1_mu REAL NOT NULL,
+    pre_i1_phi REAL NOT NULL,
+    pre_i1_sigma REAL NOT NULL,
+    pre_i2_mu REAL NOT NULL,
+    pre_i2_phi REAL NOT NULL,
+    pre_i2_sigma REAL NOT NULL,
+    post_i1_mu REAL NOT NULL,
+    post_i1_phi REAL NOT NULL,
+    post_i1_sigma REAL NOT NULL,
+    post_i2_mu REAL NOT NULL,
+    post_i2_phi REAL NOT NULL,
+    post_i2_sigma REAL NOT NULL,
+    amends INTEGER
+);
+CREATE TABLE IF NOT EXISTS judge_history (
+    judge_id TEXT NOT NULL,
+    i1 TEXT NOT NULL,
+    i2 TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS leases (
+    match_id TEXT PRIMARY KEY,
+    judge_id TEXT NOT NULL,
+    expires_at_ms INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS skips (
+    judge_id TEXT NOT NULL,
+    match_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    item_id TEXT,
+    scope TEXT,
+    timestamp_ms INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS event (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    config TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// Columns added after their table was first released, as (table, column,
+/// definition). Databases written by older versions get them on open.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("judges", "active", "INTEGER NOT NULL DEFAULT 1"),
+    ("items", "active", "INTEGER NOT NULL DEFAULT 1"),
+];
+
+/// Everything needed to rebuild a `SchedulerState` after a restart.
+pub struct Snapshot {
+    pub state: States,
+    pub paused: bool,
+    pub judges: Vec<Judge>,
+    /// (token hash, judge id) for every token issued to a judge.
+    pub judge_tokens: Vec<(String, String)>,
+    pub items: Vec<Item>,
+    /// Matches along with whether they were sitting in the priority queue.
+    pub matches: Vec<(MatchPair, bool)>,
+    pub votes: Vec<Vote>,
+    pub rating_period: u64,
+    /// Every (judge id, item, item) assignment, in the order they were made.
+    pub judge_history: Vec<(String, String, String)>,
+    pub leases: Vec<Lease>,
+    pub skips: Vec<Skip>,
+}
+
+/// Write-through SQLite store backing a `SchedulerState`.
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+fn winner_to_str(winner: MatchWinner) -> &'static str {
+    match winner {
+        MatchWinner::A => "A",
+        MatchWinner::B => "B",
+        MatchWinner::Tie => "Tie",
+        MatchWinner::LeanA => "LeanA",
+        MatchWinner::LeanB => "LeanB",
+    }
+}
+
+fn winner_from_str(s: &str) -> Option<MatchWinner> {
+    match s {
+        "A" => Some(MatchWinner::A),
+        "B" => Some(MatchWinner::B),
+        "Tie" => Some(MatchWinner::Tie),
+        "LeanA" => Some(MatchWinner::LeanA),
+        "LeanB" => Some(MatchWinner::LeanB),
+        _ => None,
+    }
+}
+
+fn scope_to_str(scope: UnavailableScope) -> &'static str {
+    match scope {
+        UnavailableScope::Judge => "judge",
+        UnavailableScope::Global => "global",
+    }
+}
+
+fn scope_from_str(s: &str) -> Option<UnavailableScope> {
+    match s {
+        "judge" => Some(UnavailableScope::Judge),
+        "global" => Some(UnavailableScope::Global),
+        _ => None,
+    }
+}
+
+fn glicko2_at(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Glicko2> {
+    Ok(Glicko2 {
+        mu: row.get(idx)?,
+        phi: row.get(idx + 1)?,
+        sigma: row.get(idx + 2)?,
+    })
+}
+
+fn write_item(conn: &Connection, item: &Item) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO items
+         (id, name, location, description, mu, phi, sigma, active)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            item.id,
+            item.name,
+            item.location,
+            item.description,
+            item.rating.mu,
+            item.rating.phi,
+            item.rating.sigma,
+            item.active
+        ],
+    )?;
+    Ok(())
+}
+
+fn write_match(conn: &Connection, m: &MatchPair, queued: bool) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO matches (id, i1, i2, visit_count, winner, judge_id, queued)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            m.match_pair_id,
+            m.i1,
+            m.i2,
+            m.visit_count,
+            m.winner.map(winner_to_str),
+            m.judge_id,
+            queued
+        ],
+    )?;
+    Ok(())
+}
+
+/// Votes are never updated once written.
+fn write_vote(conn: &Connection, vote: &Vote) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO votes VALUES
+         (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+          ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+        params![
+            vote.seq,
+            vote.judge_id,
+            vote.match_id,
+            vote.i1,
+            vote.i2,
+            winner_to_str(vote.winner),
+            vote.timestamp_ms,
+            vote.period,
+            vote.pre_i1.mu,
+            vote.pre_i1.phi,
+            vote.pre_i1.sigma,
+            vote.pre_i2.mu,
+            vote.pre_i2.phi,
+            vote.pre_i2.sigma,
+            vote.post_i1.mu,
+            vote.post_i1.phi,
+            vote.post_i1.sigma,
+            vote.post_i2.mu,
+            vote.post_i2.phi,
+            vote.post_i2.sigma,
+            vote.amends
+        ],
+    )?;
+    Ok(())
+}
+
+impl Storage {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        for (table, column, definition) in ADDED_COLUMNS {
+            let exists = conn
+                .prepare(&format!(
+                    "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+                    table
+                ))?
+                .exists([column])?;
+            if !exists {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, definition
+                ))?;
+            }
+        }
+        Ok(Storage {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn save_judge(&self, judge: &Judge) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO judges (id, email, active) VALUES (?1, ?2, ?3)",
+            params![judge.id, judge.email, judge.active],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_judge_token(&self, token_hash: &str, judge_id: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO judge_tokens (token_hash, judge_id) VALUES (?1, ?2)",
+            params![token_hash, judge_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_judge_tokens(&self, judge_id: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM judge_tokens WHERE judge_id = ?1",
+            params![judge_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_item(&self, item: &Item) -> rusqlite::Result<()> {
+        write_item(&self.conn.lock().unwrap(), item)
+    }
+
+    pub fn save_match(&self, m: &MatchPair, queued: bool) -> rusqlite::Result<()> {
+        write_match(&self.conn.lock().unwrap(), m, queued)
+    }
+
+    /// Writes a verdict in one transaction: the vote appended to the log, the
+    /// judged match, the items whose ratings it changed and, for a first
+    /// verdict, the removal of the lease it was cast under. Either all of it
+    /// is stored or none of it is, so stored ratings always match the log.
+    pub fn record_verdict(
+        &self,
+        vote: &Vote,
+        m: &MatchPair,
+        queued: bool,
+        items: &[Item],
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        write_vote(&tx, vote)?;
+        write_match(&tx, m, queued)?;
+        for item in items {
+            write_item(&tx, item)?;
+        }
+        if vote.amends.is_none() {
+            tx.execute(
+                "DELETE FROM leases WHERE match_id = ?1",
+                params![m.match_pair_id],
+            )?;
+        }
+        tx.commit()
+    }
+
+    pub fn append_judge_history(&self, judge_id: &str, i1: &str, i2: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO judge_history (judge_id, i1, i2) VALUES (?1, ?2, ?3)",
+            params![judge_id, i1, i2],
+        )?;
+        Ok(())
+    }
+
+    pub fn append_skip(&self, skip: &Skip) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO skips (judge_id, match_id, reason, item_id, scope, timestamp_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                skip.judge_id,
+                skip.match_id,
+                skip.reason.as_str(),
+                skip.unavailable.as_ref().map(|u| &u.item_id),
+                skip.unavailable.as_ref().map(|u| scope_to_str(u.scope)),
+                skip.timestamp_ms
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_event_config(&self, config: &EventConfig) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(config)
+            .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO event (id, config) VALUES (0, ?1)",
+            params![json],
+        )?;
+        Ok(())
+    }
+
+    /// The configuration of the event stored in this database, if it has one.
+    pub fn load_event_config(&self) -> rusqlite::Result<Option<EventConfig>> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row("SELECT config FROM event WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        json.map(|json| {
+            serde_json::from_str(&json).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    rusqlite::types::Type::Text,
+                    Box::new(err),
+                )
+            })
+        })
+        .transpose()
+    }
+
+    pub fn save_lease(&self, lease: &Lease) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO leases (match_id, judge_id, expires_at_ms) VALUES (?1, ?2, ?3)",
+            params![lease.match_id, lease.judge_id, lease.expires_at_ms],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_lease(&self, match_id: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM leases WHERE match_id = ?1", params![match_id])?;
+        Ok(())
+    }
+
+    fn save_meta(&self, key: &str, value: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_state(&self, state: States) -> rusqlite::Result<()> {
+        self.save_meta("state", state.as_str())
+    }
+
+    pub fn save_rating_period(&self, period: u64) -> rusqlite::Result<()> {
+        self.save_meta("rating_period", &period.to_string())
+    }
+
+    pub fn save_paused(&self, paused: bool) -> rusqlite::Result<()> {
+        self.save_meta("paused", if paused { "true" } else { "false" })
+    }
+
+    /// Deletes everything matchmaking produced, keeping items and judges.
+    pub fn reset(&self) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch(
+            "BEGIN;
+             DELETE FROM matches;
+             DELETE FROM votes;
+             DELETE FROM judge_history;
+             DELETE FROM leases;
+             DELETE FROM skips;
+             DELETE FROM meta;
+             COMMIT;",
+        )
+    }
+
+    pub fn load(&self) -> rusqlite::Result<Snapshot> {
+        let conn = self.conn.lock().unwrap();
+
+        let meta = |key: &str| -> rusqlite::Result<Option<String>> {
+            conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+        };
+        let state = meta("state")?
+            .and_then(|s| States::parse(&s))
+            .unwrap_or(States::NoState);
+        let rating_period = meta("rating_period")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        let paused = meta("paused")?.as_deref() == Some("true");
+
+        let mut stmt = conn.prepare("SELECT id, email, active FROM judges ORDER BY rowid")?;
+        let judges = stmt
+            .query_map([], |row| {
+                Ok(Judge {
+                    active: row.get(2)?,
+                    ..Judge::from_id(row.get(1)?, row.get(0)?)
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT token_hash, judge_id FROM judge_tokens")?;
+        let judge_tokens = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, name, location, description, mu, phi, sigma, active
+             FROM items ORDER BY rowid",
+        )?;
+        let items = stmt
+            .query_map([], |row| {
+                Ok(Item {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    location: row.get(2)?,
+                    description: row.get(3)?,
+                    score: 0.0,
+                    rd: 0.0,
+                    rating: glicko2_at(row, 4)?,
+                    active: row.get(7)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, i1, i2, visit_count, winner, judge_id, queued FROM matches ORDER BY rowid",
+        )?;
+        let matches = stmt
+            .query_map([], |row| {
+                let winner: Option<String> = row.get(4)?;
+                Ok((
+                    MatchPair {
+                        match_pair_id: row.get(0)?,
+                        i1: row.get(1)?,
+                        i2: row.get(2)?,
+                        visit_count: row.get(3)?,
+                        winner: winner.as_deref().and_then(winner_from_str),
+                        judge_id: row.get(5)?,
+                    },
+                    row.get(6)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT * FROM votes ORDER BY seq")?;
+        let votes = stmt
+            .query_map([], |row| {
+                let winner: String = row.get(5)?;
+                Ok(Vote {
+                    seq: row.get(0)?,
+                    judge_id: row.get(1)?,
+                    match_id: row.get(2)?,
+                    i1: row.get(3)?,
+                    i2: row.get(4)?,
+                    winner: winner_from_str(&winner).ok_or_else(|| {
+                        rusqlite::Error::InvalidColumnType(
+                            5,
+                            "winner".to_string(),
+                            rusqlite::types::Type::Text,
+                        )
+                    })?,
+                    timestamp_ms: row.get(6)?,
+                    period: row.get(7)?,
+                    pre_i1: glicko2_at(row, 8)?,
+                    pre_i2: glicko2_at(row, 11)?,
+                    post_i1: glicko2_at(row, 14)?,
+                    post_i2: glicko2_at(row, 17)?,
+                    amends: row.get(20)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT judge_id, i1, i2 FROM judge_history ORDER BY rowid")?;
+        let judge_history = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT match_id, judge_id, expires_at_ms FROM leases")?;
+        let leases = stmt
+            .query_map([], |row| {
+                Ok(Lease {
+                    match_id: row.get(0)?,
+                    judge_id: row.get(1)?,
+                    expires_at_ms: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT judge_id, match_id, reason, item_id, scope, timestamp_ms
+             FROM skips ORDER BY rowid",
+        )?;
+        let skips = stmt
+            .query_map([], |row| {
+                let reason: String = row.get(2)?;
+                let item_id: Option<String> = row.get(3)?;
+                let scope: Option<String> = row.get(4)?;
+                Ok(Skip {
+                    judge_id: row.get(0)?,
+                    match_id: row.get(1)?,
+                    reason: SkipReason::parse(&reason).unwrap_or(SkipReason::Other),
+                    unavailable: match (item_id, scope.as_deref().and_then(scope_from_str)) {
+                        (Some(item_id), Some(scope)) => Some(ItemUnavailable { item_id, scope }),
+                        _ => None,
+                    },
+                    timestamp_ms: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Snapshot {
+            state,
+            paused,
+            judges,
+            judge_tokens,
+            items,
+            matches,
+            votes,
+            rating_period,
+            judge_history,
+            leases,
+            skips,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        error::SchedulerError,
+        rating::{engine_for, EngineKind},
+        scheduler::{SchedulerOptions, SchedulerState},
+    };
+
+    #[test]
+    #[timeout(1000)]
+    fn test_roundtrip() {
+        let storage = Storage::open_in_memory().unwrap();
+        let engine = engine_for(EngineKind::Glicko2);
+
+        let judge = Judge::new("judge@example.com".to_string());
+        let item = Item::new(
+            "name".to_string(),
+            "table 1".to_string(),
+            "desc".to_string(),
+            engine.as_ref(),
+        );
+        let m = MatchPair {
+            match_pair_id: "m1".to_string(),
+            i1: item.id.clone(),
+            i2: item.id.clone(),
+            visit_count: 2,
+            winner: Some(MatchWinner::Tie),
+            judge_id: Some(judge.id.clone()),
+        };
+
+        storage.save_judge(&judge).unwrap();
+        storage.save_judge_token("hash", &judge.id).unwrap();
+        storage.save_item(&item).unwrap();
+        storage.save_match(&m, true).unwrap();
+        storage.save_state(States::Continuous).unwrap();
+        storage.save_paused(true).unwrap();
+        let skip = Skip {
+            judge_id: judge.id.clone(),
+            match_id: "m1".to_string(),
+            reason: SkipReason::ItemAbsent,
+            unavailable: Some(ItemUnavailable {
+                item_id: item.id.clone(),
+                scope: UnavailableScope::Global,
+            }),
+            timestamp_ms: 1,
+        };
+        storage.append_skip(&skip).unwrap();
+
+        let snapshot = storage.load().unwrap();
+        assert_eq!(snapshot.state, States::Continuous);
+        assert!(snapshot.paused);
+        assert_eq!(snapshot.skips, vec![skip]);
+        assert_eq!(
+            snapshot.judge_tokens,
+            vec![("hash".to_string(), judge.id.clone())]
+        );
+        assert_eq!(snapshot.judges, vec![judge]);
+        assert_eq!(snapshot.items[0].rating, item.rating);
+        assert_eq!(snapshot.matches.len(), 1);
+        assert_eq!(snapshot.matches[0].0.winner, Some(MatchWinner::Tie));
+        assert_eq!(snapshot.matches[0].0.visit_count, 2);
+        assert!(snapshot.matches[0].1);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_scheduler_rehydrate() {
+        let storage = Arc::new(Storage::open_in_memory().unwrap());
+        let engine = engine_for(EngineKind::Glicko2);
+        let options = SchedulerOptions::default();
+        let state = SchedulerState::load(engine.clone(), options.clone(), storage.clone()).unwrap();
+
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        let lost = state.issue_token(&judge.id);
+        let token = state.issue_token(&judge.id);
+        for i in 0..4 {
+            state.add_item(Item::new(
+                format!("item {}", i),
+                String::new(),
+                String::new(),
+                engine.as_ref(),
+            ));
+        }
+        state.seed_start(1).unwrap();
+        let m = state.give_judge_next_match(&judge).unwrap();
+        let pending = state.give_judge_next_match(&judge).unwrap();
+        assert!(state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .is_ok());
+
+        let restored = SchedulerState::load(engine, options, storage).unwrap();
+        assert_eq!(restored.get_state(), States::Init);
+        assert_eq!(restored.get_judges(), vec![judge.clone()]);
+        assert_eq!(restored.judge_for_token(&token), Some(judge.clone()));
+        assert_eq!(restored.judge_for_token(&lost), None);
+        let winner = restored
+            .get_items()
+            .into_iter()
+            .find(|item| item.id == m.i1)
+            .unwrap();
+        assert!(winner.score > 1500.0);
+
+        let votes = restored.get_votes();
+        assert_eq!(votes.len(), 1);
+        assert_eq!(votes[0].judge_id, judge.id);
+        assert_eq!(votes[0].post_i1, winner.rating);
+
+        let judged = restored
+            .get_matches()
+            .get(&m.match_pair_id)
+            .unwrap()
+            .clone();
+        assert_eq!(judged.winner, Some(MatchWinner::A));
+        assert_eq!(judged.visit_count, 1);
+        assert!(restored.is_queued(&m.match_pair_id));
+        assert_eq!(restored.get_judge_history(&judge.id).views(&m.i1), 1);
+
+        let leases = restored.get_leases();
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].match_id, pending.match_pair_id);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_verdict_is_stored_whole_or_not_at_all() {
+        let storage = Arc::new(Storage::open_in_memory().unwrap());
+        let engine = engine_for(EngineKind::Glicko2);
+        let state =
+            SchedulerState::load(engine.clone(), SchedulerOptions::default(), storage.clone())
+                .unwrap();
+        let judge = Judge::new("judge@example.com".to_string());
+        state.add_judge(judge.clone());
+        for i in 0..2 {
+            state.add_item(Item::new(
+                format!("item {}", i),
+                String::new(),
+                String::new(),
+                engine.as_ref(),
+            ));
+        }
+        state.seed_start(1).unwrap();
+        let m = state.give_judge_next_match(&judge).unwrap();
+        let before = state.get_items();
+
+        // the vote goes in but the ratings that follow from it cannot be written
+        let fail_items = "CREATE TRIGGER fail BEFORE INSERT ON items
+                          BEGIN SELECT RAISE(ABORT, 'disk full'); END;";
+        storage
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch(fail_items)
+            .unwrap();
+        assert!(matches!(
+            state.judge_match(&judge, &m.match_pair_id, MatchWinner::A, false),
+            Err(SchedulerError::Internal(_))
+        ));
+        assert!(state.get_votes().is_empty());
+        assert_eq!(state.get_items(), before);
+        assert_eq!(state.get_leases().len(), 1);
+        storage
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch("DROP TRIGGER fail;")
+            .unwrap();
+        let snapshot = storage.load().unwrap();
+        assert!(snapshot.votes.is_empty());
+        assert_eq!(snapshot.leases.len(), 1);
+
+        // the judge still holds the lease and can try again
+        state
+            .judge_match(&judge, &m.match_pair_id, MatchWinner::A, false)
+            .unwrap();
+        let snapshot = storage.load().unwrap();
+        assert_eq!(snapshot.votes.len(), 1);
+        assert!(snapshot.leases.is_empty());
+        let stored = snapshot.items.iter().find(|i| i.id == m.i1).unwrap();
+        assert_eq!(stored.rating, snapshot.votes[0].post_i1);
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn test_adds_missing_columns() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE judges (id TEXT PRIMARY KEY, email TEXT NOT NULL);
+             INSERT INTO judges (id, email) VALUES ('j1', 'judge@example.com');",
+        )
+        .unwrap();
+        let storage = Storage::init(conn).unwrap();
+        let snapshot = storage.load().unwrap();
+        assert!(snapshot.judges[0].active);
+
+        let mut judge = snapshot.judges[0].clone();
+        judge.active = false;
+        storage.save_judge(&judge).unwrap();
+        assert_eq!(storage.load().unwrap().judges, vec![judge]);
+    }
+}
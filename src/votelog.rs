@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    glicko2::algo::Glicko2,
+    rating::{RatingEngine, RatingMode},
+    scheduler::MatchWinner,
+};
+
+/// A single verdict as it was applied, in the order it was applied.
+///
+/// In period mode ratings only move when the period closes, so `post_*` equals
+/// `pre_*` for every vote in that mode.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vote {
+    pub seq: u64,
+    pub judge_id: String,
+    pub match_id: String,
+    pub i1: String,
+    pub i2: String,
+    pub winner: MatchWinner,
+    pub timestamp_ms: u64,
+    /// The rating period that was open when the vote was cast.
+    pub period: u64,
+    pub pre_i1: Glicko2,
+    pub pre_i2: Glicko2,
+    pub post_i1: Glicko2,
+    pub post_i2: Glicko2,
+    /// Set when this vote replaces an earlier verdict on the same match.
+    pub amends: Option<u64>,
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// The votes that currently count, in the order they count. An amendment takes
+/// the place of the vote it replaces, so fixing a verdict does not move it in time.
+pub fn effective(votes: &[Vote]) -> Vec<&Vote> {
+    let mut ordered: Vec<&Vote> = votes.iter().collect();
+    ordered.sort_by_key(|v| v.seq);
+
+    let mut root: HashMap<u64, u64> = HashMap::new();
+    let mut latest: BTreeMap<u64, &Vote> = BTreeMap::new();
+    for vote in ordered {
+        let r = match vote.amends {
+            Some(prev) => root.get(&prev).copied().unwrap_or(prev),
+            None => vote.seq,
+        };
+        root.insert(vote.seq, r);
+        latest.insert(r, vote);
+    }
+    latest.into_values().collect()
+}
+
+/// Recomputes every rating from scratch by applying the [`effective`] votes in order.
+///
+/// Every id in `item_ids` starts at the engine's initial rating, so items that
+/// were never voted on come back unchanged. In period mode only the first
+/// `closed_periods` periods are applied; votes in the open period are pending.
+pub fn replay<I>(
+    item_ids: I,
+    votes: &[Vote],
+    engine: &dyn RatingEngine,
+    mode: RatingMode,
+    closed_periods: u64,
+) -> HashMap<String, Glicko2>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut ratings: HashMap<String, Glicko2> = item_ids
+        .into_iter()
+        .map(|id| (id, engine.initial_rating()))
+        .collect();
+
+    let ordered = effective(votes);
+
+    match mode {
+        RatingMode::Immediate => {
+            for vote in ordered {
+                let r1 = *ratings
+                    .entry(vote.i1.clone())
+                    .or_insert_with(|| engine.initial_rating());
+                let r2 = *ratings
+                    .entry(vote.i2.clone())
+                    .or_insert_with(|| engine.initial_rating());
+                let (n1, n2) = engine.rate(&r1, &r2, vote.winner);
+                ratings.insert(vote.i1.clone(), n1);
+                ratings.insert(vote.i2.clone(), n2);
+            }
+        }
+        RatingMode::Period => {
+            for period in 0..closed_periods {
+                let batch: Vec<&Vote> = ordered
+                    .iter()
+                    .filter(|v| v.period == period)
+                    .copied()
+                    .collect();
+                ratings = close_period(ratings, &batch, engine);
+            }
+        }
+    }
+
+    ratings
+}
+
+/// Applies one rating period: every item is rated against the pre-period
+/// ratings of the opponents it met, and items with no games have their
+/// deviation inflated by the engine.
+pub fn close_period(
+    mut ratings: HashMap<String, Glicko2>,
+    votes: &[&Vote],
+    engine: &dyn RatingEngine,
+) -> HashMap<String, Glicko2> {
+    for vote in votes {
+        for id in [&vote.i1, &vote.i2] {
+            ratings
+                .entry(id.clone())
+                .or_insert_with(|| engine.initial_rating());
+        }
+    }
+
+    let mut games: HashMap<&str, Vec<(Glicko2, f64)>> = HashMap::new();
+    for vote in votes {
+        let s1 = vote.winner.score_a();
+        let r1 = ratings[&vote.i1];
+        let r2 = ratings[&vote.i2];
+        games.entry(&vote.i1).or_default().push((r2, s1));
+        games.entry(&vote.i2).or_default().push((r1, 1f64 - s1));
+    }
+
+    ratings
+        .iter()
+        .map(|(id, rating)| {
+            let played = games.get(id.as_str()).map(Vec::as_slice).unwrap_or(&[]);
+            (id.clone(), engine.rate_period(rating, played))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+
+    use super::*;
+    use crate::rating::{engine_for, EngineKind};
+
+    fn vote(seq: u64, i1: &str, i2: &str, winner: MatchWinner) -> Vote {
+        period_vote(seq, 0, i1, i2, winner)
+    }
+
+    fn period_vote(seq: u64, period: u64, i1: &str, i2: &str, winner: MatchWinner) -> Vote {
+        let g = Glicko2::new();
+        Vote {
+            seq,
+            judge_id: "judge".to_string(),
+            match_id: format!("m{}", seq),
+            i1: i1.to_string(),
+            i2: i2.to_string(),
+            winner,
+            timestamp_ms: 0,
+            period,
+            pre_i1: g,
+            pre_i2: g,
+            post_i1: g,
+            post_i2: g,
+            amends: None,
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_replay_matches_live_updates() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let votes = vec![
+            vote(0, "a", "b", MatchWinner::A),
+            vote(1, "b", "c", MatchWinner::A),
+            vote(2, "a", "c", MatchWinner::B),
+        ];
+
+        let mut live: HashMap<String, Glicko2> = HashMap::new();
+        for id in ["a", "b", "c"] {
+            live.insert(id.to_string(), engine.initial_rating());
+        }
+        for v in &votes {
+            let (n1, n2) = engine.rate(&live[&v.i1], &live[&v.i2], v.winner);
+            live.insert(v.i1.clone(), n1);
+            live.insert(v.i2.clone(), n2);
+        }
+
+        let replayed = replay(
+            ["a", "b", "c", "d"].map(String::from),
+            &votes,
+            engine.as_ref(),
+            RatingMode::Immediate,
+            0,
+        );
+        for id in ["a", "b", "c"] {
+            assert_eq!(replayed[id], live[id]);
+        }
+        assert_eq!(replayed["d"], engine.initial_rating());
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_replay_uses_sequence_order() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let votes = vec![
+            vote(0, "a", "b", MatchWinner::A),
+            vote(1, "a", "b", MatchWinner::B),
+        ];
+        let reversed: Vec<Vote> = votes.iter().rev().cloned().collect();
+
+        let ids = || ["a", "b"].map(String::from);
+        let mode = RatingMode::Immediate;
+        assert_eq!(
+            replay(ids(), &votes, engine.as_ref(), mode, 0),
+            replay(ids(), &reversed, engine.as_ref(), mode, 0)
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_amendment_replaces_in_place() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let mut amended = vote(2, "a", "b", MatchWinner::B);
+        amended.amends = Some(0);
+        let mut twice = vote(3, "a", "b", MatchWinner::A);
+        twice.amends = Some(2);
+
+        let votes = vec![
+            vote(0, "a", "b", MatchWinner::A),
+            vote(1, "b", "c", MatchWinner::B),
+            amended,
+            twice,
+        ];
+        let seqs: Vec<u64> = effective(&votes).iter().map(|v| v.seq).collect();
+        assert_eq!(seqs, vec![3, 1]);
+
+        let fixed = vec![
+            vote(0, "a", "b", MatchWinner::A),
+            vote(1, "b", "c", MatchWinner::B),
+        ];
+        let ids = || ["a", "b", "c"].map(String::from);
+        let mode = RatingMode::Immediate;
+        assert_eq!(
+            replay(ids(), &votes, engine.as_ref(), mode, 0),
+            replay(ids(), &fixed, engine.as_ref(), mode, 0)
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn test_replay_periods() {
+        let engine = engine_for(EngineKind::Glicko2);
+        let votes = vec![
+            period_vote(0, 0, "a", "b", MatchWinner::A),
+            period_vote(1, 0, "a", "c", MatchWinner::A),
+            period_vote(2, 1, "b", "c", MatchWinner::B),
+        ];
+        let ids = || ["a", "b", "c", "d"].map(String::from);
+
+        let start: HashMap<String, Glicko2> = ids()
+            .into_iter()
+            .map(|id| (id, engine.initial_rating()))
+            .collect();
+        let first: Vec<&Vote> = votes[..2].iter().collect();
+        let expected = close_period(start.clone(), &first, engine.as_ref());
+
+        // the second period is still open, so its vote is pending
+        let replayed = replay(ids(), &votes, engine.as_ref(), RatingMode::Period, 1);
+        assert_eq!(replayed, expected);
+        assert!(replayed["d"].phi > start["d"].phi);
+
+        // both of a's games are rated against the same pre-period ratings
+        assert_eq!(
+            replayed["a"],
+            engine.rate_period(&start["a"], &[(start["b"], 1.0), (start["c"], 1.0)])
+        );
+    }
+}